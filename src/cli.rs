@@ -0,0 +1,811 @@
+//! Command-line parsing for dirr.
+//!
+//! Every option is declared once in a [`Command`] table. The parser, the
+//! `--help` output and the usage hint printed on errors are all derived from
+//! that table, so they cannot drift apart.
+
+use crate::{
+    color::ColorChoice, layout::Placement, Charset, OutputFormat, SizeFormat, SizeUnits, TimeStyle,
+};
+use chrono::format::{Item, StrftimeItems};
+use dirr::{DiskUsage, EntryKind, Pattern, SortKey, SortOrder, TimeField};
+use std::{
+    fmt,
+    io::{self, Write as _},
+    path::PathBuf,
+};
+
+pub struct OptSpec {
+    pub long: &'static str,
+    pub short: Option<char>,
+    /// Name of the value the option takes, or `None` for a plain flag.
+    pub value: Option<&'static str>,
+    pub help: &'static str,
+}
+
+pub struct Command {
+    pub name: &'static str,
+    pub about: &'static str,
+    pub usage: &'static str,
+    pub options: &'static [OptSpec],
+    pub subcommands: &'static [Command],
+    pub after_help: &'static str,
+}
+
+pub const DIRR: Command = Command {
+    name: "dirr",
+    about: "A simple directory listing tool with exclusions and metadata support",
+    usage: "dirr [OPTIONS] [PATHS]...",
+    options: &[
+        OptSpec {
+            long: "help",
+            short: Some('h'),
+            value: None,
+            help: "Shows this help message.",
+        },
+        OptSpec {
+            long: "meta",
+            short: Some('m'),
+            value: None,
+            help: "Shows metadata (file size and the time picked by --time-field) alongside the directory listing.",
+        },
+        OptSpec {
+            long: "long",
+            short: Some('l'),
+            value: None,
+            help: "Shows mode, hard links, owner, group, inode and device in columns before each entry, like ls -l.",
+        },
+        OptSpec {
+            long: "layout",
+            short: None,
+            value: Some("PLACEMENT"),
+            help: "Where the -m, -l and --du columns go: 'gutter' (default, before the tree) or 'right' (right-justified to the terminal width).",
+        },
+        OptSpec {
+            long: "du",
+            short: None,
+            value: None,
+            help: "Shows each directory's total size: everything beneath it that is not excluded.",
+        },
+        OptSpec {
+            long: "blocks",
+            short: None,
+            value: None,
+            help: "Adds up allocated disk blocks instead of apparent sizes, like du. Implies --du.",
+        },
+        OptSpec {
+            long: "size-format",
+            short: None,
+            value: Some("UNITS"),
+            help: "Units for sizes: 'iec' (default, KiB, MiB...), 'si' (kB, MB...) or 'bytes'.",
+        },
+        OptSpec {
+            long: "precision",
+            short: None,
+            value: Some("N"),
+            help: "Digits shown after the decimal point in sizes. Defaults to 2.",
+        },
+        OptSpec {
+            long: "thousands",
+            short: None,
+            value: None,
+            help: "Groups digits in sizes with commas, e.g. '12,768 B'.",
+        },
+        OptSpec {
+            long: "time-style",
+            short: None,
+            value: Some("STYLE"),
+            help: "How -m shows times: 'relative' (default), 'iso', 'full' or '+FORMAT' with strftime fields such as '+%Y-%m-%d %H:%M'.",
+        },
+        OptSpec {
+            long: "time-field",
+            short: None,
+            value: Some("FIELD"),
+            help: "Which time -m shows: 'mtime' (modified, default), 'atime' (accessed), 'btime' (created) or 'ctime' (changed).",
+        },
+        OptSpec {
+            long: "utc",
+            short: None,
+            value: None,
+            help: "Shows times in UTC instead of the local time zone.",
+        },
+        OptSpec {
+            long: "all",
+            short: Some('a'),
+            value: None,
+            help: "Lists hidden entries too: dotfiles and files the system marks as hidden.",
+        },
+        OptSpec {
+            long: "exclude",
+            short: Some('x'),
+            value: Some("PATTERN"),
+            help: "Excludes entries matching PATTERN, a glob such as '*.tmp' or 'src/generated'. Prefix with 're:' for a regex. Can be repeated.",
+        },
+        OptSpec {
+            long: "include",
+            short: Some('P'),
+            value: Some("PATTERN"),
+            help: "Lists only entries matching PATTERN, plus the directories leading to them. Can be repeated.",
+        },
+        OptSpec {
+            long: "type",
+            short: Some('t'),
+            value: Some("TYPES"),
+            help: "Lists only entries of the given types, plus the directories leading to them: 'f' (file), 'd' (directory) or 'l' (symlink), comma-separated.",
+        },
+        OptSpec {
+            long: "ext",
+            short: Some('e'),
+            value: Some("EXTS"),
+            help: "Lists only files with one of the comma-separated extensions, plus the directories leading to them.",
+        },
+        OptSpec {
+            long: "regex",
+            short: None,
+            value: None,
+            help: "Treats every PATTERN as a regex unless it starts with 'glob:'.",
+        },
+        OptSpec {
+            long: "no-ignore",
+            short: None,
+            value: None,
+            help: "Lists entries even if .gitignore, .ignore or .dirrignore files exclude them.",
+        },
+        OptSpec {
+            long: "depth",
+            short: Some('L'),
+            value: Some("N"),
+            help: "Descends at most N levels below each root.",
+        },
+        OptSpec {
+            long: "max-entries",
+            short: None,
+            value: Some("N"),
+            help: "Shows at most N entries of each directory, summarising the rest on one line.",
+        },
+        OptSpec {
+            long: "sort",
+            short: None,
+            value: Some("KEY"),
+            help: "Sorts entries by 'name' (default), 'size', 'mtime', 'atime', 'btime', 'ctime', 'time' (the --time-field), 'ext' or 'none'.",
+        },
+        OptSpec {
+            long: "reverse",
+            short: Some('r'),
+            value: None,
+            help: "Reverses the sort order.",
+        },
+        OptSpec {
+            long: "dirs-first",
+            short: None,
+            value: None,
+            help: "Lists directories before files.",
+        },
+        OptSpec {
+            long: "follow",
+            short: None,
+            value: None,
+            help: "Descends into symlinked directories, skipping links that loop back.",
+        },
+        OptSpec {
+            long: "full-path",
+            short: None,
+            value: None,
+            help: "Prints each entry's path relative to its root instead of just its name.",
+        },
+        OptSpec {
+            long: "counts",
+            short: None,
+            value: None,
+            help: "Shows how many directories and files each directory contains.",
+        },
+        OptSpec {
+            long: "no-report",
+            short: None,
+            value: None,
+            help: "Leaves out the summary of directories, files and size after the tree.",
+        },
+        OptSpec {
+            long: "format",
+            short: None,
+            value: Some("FORMAT"),
+            help: "Output format: 'tree' (default), 'json' or 'ndjson' (one record per line, streamed).",
+        },
+        OptSpec {
+            long: "color",
+            short: None,
+            value: Some("WHEN"),
+            help: "Colours names by type, permissions and extension using LS_COLORS: 'auto' (default, when printing to a terminal and NO_COLOR is unset), 'always' or 'never'.",
+        },
+        OptSpec {
+            long: "charset",
+            short: None,
+            value: Some("CHARSET"),
+            help: "Characters used to draw the tree: 'utf8' (default) or 'ascii'.",
+        },
+    ],
+    subcommands: &[],
+    after_help: "\
+Paths:
+  Each PATH is printed as its own tree. Defaults to the current directory.
+  Use `--` to pass paths that start with a dash.
+
+Patterns:
+  Globs support '*', '?', '[a-z]', '[!a-z]' and '**'. Quote them so the shell
+  does not expand them first. A glob containing '/' matches the path relative
+  to the root ('/src/generated', 'docs/**/*.png'); others match names at any
+  depth. Prefix a pattern with 'path:' or 'name:' to choose explicitly.
+
+Examples:
+  dirr -m -x '*tmp*'
+    This will list all directories excluding those that have 'tmp' in their name and will show file metadata.
+  dirr src tests -x target -x 're:^\\.git$'
+    This will print one tree for 'src' and one for 'tests', excluding 'target' and '.git'.",
+};
+
+#[derive(Debug)]
+pub enum CliError {
+    UnknownOption(String),
+    MissingValue(String),
+    UnexpectedValue(String),
+    InvalidValue {
+        option: String,
+        value: String,
+        reason: String,
+    },
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::UnknownOption(opt) => write!(f, "unknown option '{}'", opt),
+            CliError::MissingValue(opt) => write!(f, "option '{}' requires a value", opt),
+            CliError::UnexpectedValue(opt) => write!(f, "option '{}' does not take a value", opt),
+            CliError::InvalidValue {
+                option,
+                value,
+                reason,
+            } => write!(f, "invalid value '{}' for '{}': {}", value, option, reason),
+        }
+    }
+}
+
+impl std::error::Error for CliError {}
+
+/// The raw result of parsing a command line against a [`Command`].
+pub struct Matches {
+    /// Options in the order they were given, with their value if they take one.
+    pub options: Vec<(&'static OptSpec, Option<String>)>,
+    pub positionals: Vec<String>,
+    /// The subcommand that was invoked, with the matches for its own arguments.
+    pub subcommand: Option<(&'static str, Box<Matches>)>,
+}
+
+impl Command {
+    fn find_long(&self, name: &str) -> Option<&'static OptSpec> {
+        self.options.iter().find(|o| o.long == name)
+    }
+
+    fn find_short(&self, c: char) -> Option<&'static OptSpec> {
+        self.options.iter().find(|o| o.short == Some(c))
+    }
+
+    fn find_subcommand(&self, name: &str) -> Option<&'static Command> {
+        self.subcommands.iter().find(|c| c.name == name)
+    }
+}
+
+/// Parses `args` (without the program name) against `command`.
+///
+/// Supports `--long`, `--long=value`, `--long value`, `-s value`, `-svalue`,
+/// clustered short flags (`-mx PATTERN`) and a `--` terminator. The first
+/// positional argument that names a subcommand hands the rest of the line to
+/// that subcommand.
+pub fn parse_args(command: &'static Command, args: &[String]) -> Result<Matches, CliError> {
+    let mut matches = Matches {
+        options: Vec::new(),
+        positionals: Vec::new(),
+        subcommand: None,
+    };
+
+    let mut i = 0;
+    while i < args.len() {
+        let arg = &args[i];
+        i += 1;
+
+        if arg == "--" {
+            matches.positionals.extend(args[i..].iter().cloned());
+            break;
+        } else if let Some(long) = arg.strip_prefix("--") {
+            let (name, inline) = match long.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (long, None),
+            };
+            let spec = command
+                .find_long(name)
+                .ok_or_else(|| CliError::UnknownOption(format!("--{}", name)))?;
+            let value = match (spec.value, inline) {
+                (None, Some(_)) => return Err(CliError::UnexpectedValue(format!("--{}", name))),
+                (None, None) => None,
+                (Some(_), Some(value)) => Some(value),
+                (Some(_), None) => {
+                    let value = args
+                        .get(i)
+                        .ok_or_else(|| CliError::MissingValue(format!("--{}", name)))?;
+                    i += 1;
+                    Some(value.clone())
+                }
+            };
+            matches.options.push((spec, value));
+        } else if arg.len() > 1 && arg.starts_with('-') {
+            let cluster = &arg[1..];
+            for (pos, c) in cluster.char_indices() {
+                let spec = command
+                    .find_short(c)
+                    .ok_or_else(|| CliError::UnknownOption(format!("-{}", c)))?;
+                if spec.value.is_none() {
+                    matches.options.push((spec, None));
+                    continue;
+                }
+                let rest = &cluster[pos + c.len_utf8()..];
+                let value = if !rest.is_empty() {
+                    rest.to_string()
+                } else {
+                    let value = args
+                        .get(i)
+                        .ok_or_else(|| CliError::MissingValue(format!("-{}", c)))?;
+                    i += 1;
+                    value.clone()
+                };
+                matches.options.push((spec, Some(value)));
+                break;
+            }
+        } else if let Some(sub) = command
+            .find_subcommand(arg)
+            .filter(|_| matches.positionals.is_empty())
+        {
+            matches.subcommand = Some((sub.name, Box::new(parse_args(sub, &args[i..])?)));
+            break;
+        } else {
+            matches.positionals.push(arg.clone());
+        }
+    }
+
+    Ok(matches)
+}
+
+/// Settings for a dirr run, built from the parsed command line.
+pub struct Config {
+    pub help: bool,
+    pub show_meta: bool,
+    pub show_hidden: bool,
+    pub long: bool,
+    pub placement: Placement,
+    pub disk_usage: Option<DiskUsage>,
+    pub size_format: SizeFormat,
+    pub time_style: TimeStyle,
+    pub time_field: TimeField,
+    pub utc: bool,
+    pub exclude: Vec<Pattern>,
+    pub include: Vec<Pattern>,
+    pub kinds: Vec<EntryKind>,
+    pub extensions: Vec<String>,
+    pub ignore_files: bool,
+    pub format: OutputFormat,
+    pub charset: Charset,
+    pub color: ColorChoice,
+    pub full_path: bool,
+    pub dir_counts: bool,
+    pub report: bool,
+    pub max_depth: Option<usize>,
+    pub max_entries: Option<usize>,
+    pub sort: SortOrder,
+    pub follow: bool,
+    pub roots: Vec<PathBuf>,
+}
+
+impl Config {
+    pub fn from_args(args: &[String]) -> Result<Config, CliError> {
+        let matches = parse_args(&DIRR, args)?;
+
+        let mut config = Config {
+            help: false,
+            show_meta: false,
+            show_hidden: false,
+            long: false,
+            placement: Placement::Gutter,
+            disk_usage: None,
+            size_format: SizeFormat {
+                units: SizeUnits::Iec,
+                precision: 2,
+                separators: false,
+            },
+            time_style: TimeStyle::Relative,
+            time_field: TimeField::Mtime,
+            utc: false,
+            exclude: Vec::new(),
+            include: Vec::new(),
+            kinds: Vec::new(),
+            extensions: Vec::new(),
+            ignore_files: true,
+            format: OutputFormat::Tree,
+            charset: Charset::Utf8,
+            color: ColorChoice::Auto,
+            full_path: false,
+            dir_counts: false,
+            report: true,
+            max_depth: None,
+            max_entries: None,
+            sort: SortOrder {
+                key: SortKey::Name,
+                reverse: false,
+                dirs_first: false,
+            },
+            follow: false,
+            roots: Vec::new(),
+        };
+
+        // Patterns are compiled once all options are known, since `--regex`
+        // may come after them.
+        let mut patterns = Vec::new();
+        let mut regex = false;
+        // `--sort time` follows `--time-field`, wherever that is given.
+        let mut sort_by_time_field = false;
+
+        for (spec, value) in &matches.options {
+            let value = value.as_deref().unwrap_or_default();
+            match spec.long {
+                "help" => config.help = true,
+                "meta" => config.show_meta = true,
+                "all" => config.show_hidden = true,
+                "long" => config.long = true,
+                "layout" => config.placement = parse_placement(spec, value)?,
+                "du" => {
+                    config.disk_usage.get_or_insert(DiskUsage::Apparent);
+                }
+                "blocks" => config.disk_usage = Some(DiskUsage::Allocated),
+                "size-format" => config.size_format.units = parse_size_units(spec, value)?,
+                "precision" => config.size_format.precision = parse_precision(spec, value)?,
+                "thousands" => config.size_format.separators = true,
+                "time-style" => config.time_style = parse_time_style(spec, value)?,
+                "time-field" => config.time_field = parse_time_field(spec, value)?,
+                "utc" => config.utc = true,
+                "exclude" | "include" => patterns.push((spec, value)),
+                "type" => {
+                    for kind in value.split(',') {
+                        config.kinds.push(parse_kind(spec, kind)?);
+                    }
+                }
+                "ext" => config.extensions.extend(
+                    value
+                        .split(',')
+                        .filter(|ext| !ext.is_empty())
+                        .map(String::from),
+                ),
+                "regex" => regex = true,
+                "no-ignore" => config.ignore_files = false,
+                "depth" => config.max_depth = Some(parse_depth(spec, value)?),
+                "max-entries" => config.max_entries = Some(parse_max_entries(spec, value)?),
+                "sort" => {
+                    sort_by_time_field = value == "time";
+                    if !sort_by_time_field {
+                        config.sort.key = parse_sort_key(spec, value)?;
+                    }
+                }
+                "reverse" => config.sort.reverse = true,
+                "dirs-first" => config.sort.dirs_first = true,
+                "follow" => config.follow = true,
+                "full-path" => config.full_path = true,
+                "counts" => config.dir_counts = true,
+                "no-report" => config.report = false,
+                "format" => config.format = parse_format(spec, value)?,
+                "charset" => config.charset = parse_charset(spec, value)?,
+                "color" => config.color = parse_color(spec, value)?,
+                _ => unreachable!("option --{} has no handler", spec.long),
+            }
+        }
+
+        if sort_by_time_field {
+            config.sort.key = config.time_field.into();
+        }
+
+        for (spec, value) in patterns {
+            let pattern =
+                Pattern::parse(value, regex).map_err(|e| invalid_value(spec, value, e))?;
+            match spec.long {
+                "include" => config.include.push(pattern),
+                _ => config.exclude.push(pattern),
+            }
+        }
+
+        config.roots = matches.positionals.iter().map(PathBuf::from).collect();
+        if config.roots.is_empty() {
+            config.roots.push(PathBuf::from("."));
+        }
+
+        Ok(config)
+    }
+}
+
+fn invalid_value(spec: &OptSpec, value: &str, reason: impl fmt::Display) -> CliError {
+    CliError::InvalidValue {
+        option: format!("--{}", spec.long),
+        value: value.to_string(),
+        reason: reason.to_string(),
+    }
+}
+
+fn parse_kind(spec: &OptSpec, value: &str) -> Result<EntryKind, CliError> {
+    match value {
+        "f" | "file" => Ok(EntryKind::File),
+        "d" | "dir" | "directory" => Ok(EntryKind::Dir),
+        "l" | "link" | "symlink" => Ok(EntryKind::Symlink),
+        _ => Err(invalid_value(spec, value, "expected 'f', 'd' or 'l'")),
+    }
+}
+
+fn parse_depth(spec: &OptSpec, value: &str) -> Result<usize, CliError> {
+    match value.parse::<usize>() {
+        Ok(0) => Err(invalid_value(spec, value, "depth must be greater than 0")),
+        Ok(depth) => Ok(depth),
+        Err(e) => Err(invalid_value(spec, value, e)),
+    }
+}
+
+fn parse_placement(spec: &OptSpec, value: &str) -> Result<Placement, CliError> {
+    match value {
+        "gutter" => Ok(Placement::Gutter),
+        "right" => Ok(Placement::Right),
+        _ => Err(invalid_value(spec, value, "expected 'gutter' or 'right'")),
+    }
+}
+
+fn parse_size_units(spec: &OptSpec, value: &str) -> Result<SizeUnits, CliError> {
+    match value {
+        "iec" => Ok(SizeUnits::Iec),
+        "si" => Ok(SizeUnits::Si),
+        "bytes" => Ok(SizeUnits::Bytes),
+        _ => Err(invalid_value(
+            spec,
+            value,
+            "expected 'iec', 'si' or 'bytes'",
+        )),
+    }
+}
+
+fn parse_precision(spec: &OptSpec, value: &str) -> Result<usize, CliError> {
+    match value.parse::<usize>() {
+        Ok(precision) if precision <= 9 => Ok(precision),
+        Ok(_) => Err(invalid_value(spec, value, "precision must be at most 9")),
+        Err(e) => Err(invalid_value(spec, value, e)),
+    }
+}
+
+fn parse_time_style(spec: &OptSpec, value: &str) -> Result<TimeStyle, CliError> {
+    match value {
+        "relative" => Ok(TimeStyle::Relative),
+        "iso" => Ok(TimeStyle::Iso),
+        "full" => Ok(TimeStyle::Full),
+        _ => match value.strip_prefix('+') {
+            Some(format) if StrftimeItems::new(format).any(|item| item == Item::Error) => {
+                Err(invalid_value(spec, value, "not a valid strftime format"))
+            }
+            Some(format) => Ok(TimeStyle::Custom(format.to_string())),
+            None => Err(invalid_value(
+                spec,
+                value,
+                "expected 'relative', 'iso', 'full' or '+FORMAT'",
+            )),
+        },
+    }
+}
+
+fn parse_max_entries(spec: &OptSpec, value: &str) -> Result<usize, CliError> {
+    match value.parse::<usize>() {
+        Ok(0) => Err(invalid_value(spec, value, "must be greater than 0")),
+        Ok(max) => Ok(max),
+        Err(e) => Err(invalid_value(spec, value, e)),
+    }
+}
+
+fn parse_sort_key(spec: &OptSpec, value: &str) -> Result<SortKey, CliError> {
+    match value {
+        "name" => Ok(SortKey::Name),
+        "size" => Ok(SortKey::Size),
+        "mtime" => Ok(SortKey::Mtime),
+        "atime" => Ok(SortKey::Atime),
+        "btime" => Ok(SortKey::Btime),
+        "ctime" => Ok(SortKey::Ctime),
+        "ext" => Ok(SortKey::Ext),
+        "none" => Ok(SortKey::None),
+        _ => Err(invalid_value(
+            spec,
+            value,
+            "expected 'name', 'size', 'mtime', 'atime', 'btime', 'ctime', 'time', 'ext' or 'none'",
+        )),
+    }
+}
+
+fn parse_time_field(spec: &OptSpec, value: &str) -> Result<TimeField, CliError> {
+    match value {
+        "mtime" => Ok(TimeField::Mtime),
+        "atime" => Ok(TimeField::Atime),
+        "btime" => Ok(TimeField::Btime),
+        "ctime" => Ok(TimeField::Ctime),
+        _ => Err(invalid_value(
+            spec,
+            value,
+            "expected 'mtime', 'atime', 'btime' or 'ctime'",
+        )),
+    }
+}
+
+fn parse_format(spec: &OptSpec, value: &str) -> Result<OutputFormat, CliError> {
+    match value {
+        "tree" => Ok(OutputFormat::Tree),
+        "json" => Ok(OutputFormat::Json),
+        "ndjson" => Ok(OutputFormat::Ndjson),
+        _ => Err(invalid_value(
+            spec,
+            value,
+            "expected 'tree', 'json' or 'ndjson'",
+        )),
+    }
+}
+
+fn parse_charset(spec: &OptSpec, value: &str) -> Result<Charset, CliError> {
+    match value {
+        "utf8" | "utf-8" | "unicode" => Ok(Charset::Utf8),
+        "ascii" => Ok(Charset::Ascii),
+        _ => Err(invalid_value(spec, value, "expected 'utf8' or 'ascii'")),
+    }
+}
+
+fn parse_color(spec: &OptSpec, value: &str) -> Result<ColorChoice, CliError> {
+    match value {
+        "always" => Ok(ColorChoice::Always),
+        "never" => Ok(ColorChoice::Never),
+        "auto" => Ok(ColorChoice::Auto),
+        _ => Err(invalid_value(
+            spec,
+            value,
+            "expected 'always', 'never' or 'auto'",
+        )),
+    }
+}
+
+fn option_label(spec: &OptSpec) -> String {
+    let mut label = match spec.short {
+        Some(c) => format!("-{}, --{}", c, spec.long),
+        None => format!("    --{}", spec.long),
+    };
+    if let Some(value) = spec.value {
+        label.push_str(&format!(" <{}>", value));
+    }
+    label
+}
+
+pub fn print_help(command: &Command) -> io::Result<()> {
+    let mut out = io::stdout().lock();
+    writeln!(out, "{} - {}", command.name, command.about)?;
+    writeln!(out)?;
+    writeln!(out, "Usage:")?;
+    writeln!(out, "  {}", command.usage)?;
+    writeln!(out)?;
+    writeln!(out, "Options:")?;
+    let labels: Vec<String> = command.options.iter().map(option_label).collect();
+    let width = labels.iter().map(|l| l.len()).max().unwrap_or(0);
+    for (label, spec) in labels.iter().zip(command.options) {
+        writeln!(out, "  {:width$}  {}", label, spec.help, width = width)?;
+    }
+    if !command.subcommands.is_empty() {
+        writeln!(out)?;
+        writeln!(out, "Commands:")?;
+        let width = command
+            .subcommands
+            .iter()
+            .map(|c| c.name.len())
+            .max()
+            .unwrap_or(0);
+        for sub in command.subcommands {
+            writeln!(out, "  {:width$}  {}", sub.name, sub.about, width = width)?;
+        }
+    }
+    if !command.after_help.is_empty() {
+        writeln!(out)?;
+        writeln!(out, "{}", command.after_help)?;
+    }
+    Ok(())
+}
+
+/// Short usage text printed after a command-line error.
+pub fn usage_hint(command: &Command) -> String {
+    let options: Vec<String> = command
+        .options
+        .iter()
+        .map(|spec| option_label(spec).trim_start().to_string())
+        .collect();
+    format!(
+        "Usage: {}\nOptions: {}\n\nFor more information, try '--help'.",
+        command.usage,
+        options.join(", ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(args: &[&str]) -> Result<Config, CliError> {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        Config::from_args(&args)
+    }
+
+    fn excludes(config: &Config) -> Vec<String> {
+        config.exclude.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn long_options_take_inline_or_separate_values() {
+        assert_eq!(config(&["--depth=2"]).unwrap().max_depth, Some(2));
+        assert_eq!(config(&["--depth", "3"]).unwrap().max_depth, Some(3));
+        assert_eq!(excludes(&config(&["--exclude=a=b"]).unwrap()), ["a=b"]);
+    }
+
+    #[test]
+    fn short_options_take_attached_or_separate_values() {
+        assert_eq!(config(&["-L4"]).unwrap().max_depth, Some(4));
+        assert_eq!(config(&["-L", "5"]).unwrap().max_depth, Some(5));
+        assert_eq!(excludes(&config(&["-xtarget"]).unwrap()), ["target"]);
+    }
+
+    #[test]
+    fn clustered_short_options() {
+        let meta = config(&["-mx", "tmp"]).unwrap();
+        assert!(meta.show_meta);
+        assert_eq!(excludes(&meta), ["tmp"]);
+
+        let all = config(&["-max*.log"]).unwrap();
+        assert!(all.show_meta && all.show_hidden);
+        assert_eq!(excludes(&all), ["*.log"]);
+    }
+
+    #[test]
+    fn terminator_ends_options() {
+        let config = config(&["-m", "--", "-x", "--meta"]).unwrap();
+        assert!(config.show_meta);
+        assert!(config.exclude.is_empty());
+        assert_eq!(config.roots, [PathBuf::from("-x"), PathBuf::from("--meta")]);
+    }
+
+    #[test]
+    fn repeated_options_accumulate() {
+        let config = config(&["-x", "a", "--exclude", "b", "-xc"]).unwrap();
+        assert_eq!(excludes(&config), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn value_does_not_swallow_the_next_flag() {
+        let config = config(&["-x", "foo", "-m"]).unwrap();
+        assert_eq!(excludes(&config), ["foo"]);
+        assert!(config.show_meta);
+        assert_eq!(config.roots, [PathBuf::from(".")]);
+    }
+
+    #[test]
+    fn errors() {
+        assert!(matches!(config(&["-x"]), Err(CliError::MissingValue(o)) if o == "-x"));
+        assert!(
+            matches!(config(&["src", "--exclude"]), Err(CliError::MissingValue(o)) if o == "--exclude")
+        );
+        assert!(matches!(config(&["--nope"]), Err(CliError::UnknownOption(o)) if o == "--nope"));
+        assert!(matches!(config(&["-mZ"]), Err(CliError::UnknownOption(o)) if o == "-Z"));
+        assert!(
+            matches!(config(&["--meta=1"]), Err(CliError::UnexpectedValue(o)) if o == "--meta")
+        );
+        assert!(matches!(
+            config(&["--depth", "x"]),
+            Err(CliError::InvalidValue { option, .. }) if option == "--depth"
+        ));
+    }
+}
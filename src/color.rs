@@ -0,0 +1,185 @@
+//! Coloured entry names, configured like `ls` through `LS_COLORS`.
+
+use dirr::Entry;
+use std::{
+    collections::HashMap,
+    env,
+    fs::{self, Metadata},
+    io::IsTerminal,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ColorChoice {
+    Always,
+    Never,
+    /// Colour when stdout is a terminal and `NO_COLOR` is not set.
+    Auto,
+}
+
+impl ColorChoice {
+    pub fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+                    && std::io::stdout().is_terminal()
+            }
+        }
+    }
+}
+
+/// The colours GNU `ls` uses when `LS_COLORS` is not set.
+const DEFAULT_COLORS: &str = "di=01;34:ln=01;36:pi=40;33:so=01;35:do=01;35:bd=40;33;01:\
+cd=40;33;01:or=40;31;01:su=37;41:sg=30;43:tw=30;42:ow=34;42:st=37;44:ex=01;32";
+
+/// SGR codes for each kind of entry and for name suffixes, parsed from an
+/// `LS_COLORS` value such as `di=01;34:ln=target:*.tar=01;31`.
+pub struct Colors {
+    /// Keyed by the two-letter indicator, e.g. `di` for directories.
+    indicators: HashMap<String, String>,
+    /// `*suffix` rules in the order given. Later rules win, as in `ls`.
+    suffixes: Vec<(String, String)>,
+}
+
+impl Colors {
+    /// Reads `LS_COLORS`, falling back to the `ls` defaults if it is unset.
+    pub fn from_env() -> Colors {
+        match env::var("LS_COLORS") {
+            Ok(spec) if !spec.is_empty() => Colors::parse(&spec),
+            _ => Colors::parse(DEFAULT_COLORS),
+        }
+    }
+
+    fn parse(spec: &str) -> Colors {
+        let mut colors = Colors {
+            indicators: HashMap::new(),
+            suffixes: Vec::new(),
+        };
+        for (key, code) in spec.split(':').filter_map(|item| item.split_once('=')) {
+            match key.strip_prefix('*') {
+                Some(suffix) => colors.suffixes.push((suffix.to_string(), code.to_string())),
+                None => {
+                    colors.indicators.insert(key.to_string(), code.to_string());
+                }
+            }
+        }
+        colors
+    }
+
+    /// Wraps `text` in the colour for `entry`, if it has one.
+    pub fn paint(&self, entry: &Entry, text: &str) -> String {
+        match self.code_for(entry).filter(|c| !is_reset(c)) {
+            Some(code) => format!("\x1b[{}m{}\x1b[0m", code, text),
+            None => text.to_string(),
+        }
+    }
+
+    fn code_for(&self, entry: &Entry) -> Option<&str> {
+        let metadata = entry.metadata()?;
+        if entry.is_symlink() {
+            if entry.is_broken_link() {
+                if let Some(code) = self.indicator("or") {
+                    return Some(code);
+                }
+            }
+            // `ln=target` colours a link like whatever it points to.
+            if self.indicator("ln") != Some("target") {
+                return self.indicator("ln");
+            }
+            let target = fs::metadata(entry.path()).ok()?;
+            return self.code_for_metadata(&target, &entry.file_name());
+        }
+        self.code_for_metadata(metadata, &entry.file_name())
+    }
+
+    fn code_for_metadata(&self, metadata: &Metadata, name: &str) -> Option<&str> {
+        let indicator = indicator(metadata);
+        if indicator == "fi" {
+            if let Some(code) = self.suffix(name) {
+                return Some(code);
+            }
+        }
+        self.indicator(indicator)
+            .or_else(|| self.indicator(fallback(indicator)))
+    }
+
+    fn indicator(&self, key: &str) -> Option<&str> {
+        self.indicators.get(key).map(String::as_str)
+    }
+
+    /// The colour of the last suffix rule matching `name`. Suffixes are
+    /// compared exactly first, then ignoring ASCII case.
+    fn suffix(&self, name: &str) -> Option<&str> {
+        let rules = self.suffixes.iter().rev();
+        rules
+            .clone()
+            .find(|(suffix, _)| name.ends_with(suffix.as_str()))
+            .or_else(|| {
+                let lower = name.to_ascii_lowercase();
+                rules
+                    .clone()
+                    .find(|(suffix, _)| lower.ends_with(&suffix.to_ascii_lowercase()))
+            })
+            .map(|(_, code)| code.as_str())
+    }
+}
+
+/// Whether `code` turns colour off rather than on.
+fn is_reset(code: &str) -> bool {
+    code.is_empty() || code.trim_start_matches('0').is_empty()
+}
+
+/// The `LS_COLORS` indicator for a file that is not a symbolic link.
+#[cfg(unix)]
+fn indicator(metadata: &Metadata) -> &'static str {
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+
+    let file_type = metadata.file_type();
+    let mode = metadata.permissions().mode();
+    let sticky = mode & 0o1000 != 0;
+    let other_writable = mode & 0o002 != 0;
+    if file_type.is_dir() {
+        match (sticky, other_writable) {
+            (true, true) => "tw",
+            (false, true) => "ow",
+            (true, false) => "st",
+            (false, false) => "di",
+        }
+    } else if file_type.is_fifo() {
+        "pi"
+    } else if file_type.is_socket() {
+        "so"
+    } else if file_type.is_block_device() {
+        "bd"
+    } else if file_type.is_char_device() {
+        "cd"
+    } else if mode & 0o4000 != 0 {
+        "su"
+    } else if mode & 0o2000 != 0 {
+        "sg"
+    } else if mode & 0o111 != 0 {
+        "ex"
+    } else {
+        "fi"
+    }
+}
+
+#[cfg(not(unix))]
+fn indicator(metadata: &Metadata) -> &'static str {
+    if metadata.is_dir() {
+        "di"
+    } else {
+        "fi"
+    }
+}
+
+/// The indicator to use when `LS_COLORS` sets no colour for `indicator`:
+/// special directories look like directories, special files like files.
+fn fallback(indicator: &str) -> &'static str {
+    match indicator {
+        "tw" | "ow" | "st" => "di",
+        "su" | "sg" | "ex" => "fi",
+        _ => "no",
+    }
+}
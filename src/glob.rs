@@ -0,0 +1,198 @@
+//! Shell-style glob patterns, as used by `.gitignore`.
+
+use regex::Regex;
+use std::fmt;
+
+/// A compiled glob pattern, matched against a whole `/`-separated path.
+///
+/// * `*` matches any run of characters except `/`, `?` any one of them.
+/// * `[abc]`, `[a-z]` and `[!a-z]` match one character from (or not from) a set.
+/// * `**/` at the start, `/**/` in the middle and `/**` at the end match any
+///   number of directories. Elsewhere `**` behaves like `*`.
+/// * `\` makes the next character literal.
+#[derive(Clone, Debug)]
+pub struct Glob {
+    pattern: String,
+    regex: Regex,
+}
+
+impl Glob {
+    pub fn new(pattern: &str) -> Result<Glob, regex::Error> {
+        let regex = Regex::new(&format!("^{}$", translate(pattern)))?;
+        Ok(Glob {
+            pattern: pattern.to_string(),
+            regex,
+        })
+    }
+
+    pub fn is_match(&self, path: &str) -> bool {
+        self.regex.is_match(path)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+}
+
+impl fmt::Display for Glob {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.pattern)
+    }
+}
+
+/// Translates a glob into an unanchored regex.
+fn translate(pattern: &str) -> String {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut out = String::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        match chars[i] {
+            '*' if chars.get(i + 1) == Some(&'*') => {
+                let at_start = i == 0 || chars[i - 1] == '/';
+                let at_end = i + 2 == chars.len();
+                let slash_after = chars.get(i + 2) == Some(&'/');
+                if at_start && slash_after {
+                    // `**/` matches zero or more leading directories.
+                    out.push_str("(?:.*/)?");
+                    i += 3;
+                } else if at_start && at_end {
+                    out.push_str(".*");
+                    i += 2;
+                } else {
+                    out.push_str("[^/]*");
+                    i += 2;
+                }
+            }
+            '*' => {
+                out.push_str("[^/]*");
+                i += 1;
+            }
+            '?' => {
+                out.push_str("[^/]");
+                i += 1;
+            }
+            '[' => match translate_class(&chars[i..]) {
+                Some((class, len)) => {
+                    out.push_str(&class);
+                    i += len;
+                }
+                None => {
+                    out.push_str(r"\[");
+                    i += 1;
+                }
+            },
+            '\\' if i + 1 < chars.len() => {
+                out.push_str(&regex::escape(&chars[i + 1].to_string()));
+                i += 2;
+            }
+            c => {
+                out.push_str(&regex::escape(&c.to_string()));
+                i += 1;
+            }
+        }
+    }
+
+    out
+}
+
+/// Translates a `[...]` set starting at `chars[0]`. Returns the regex class
+/// and how many pattern characters it used, or `None` if it is not closed.
+fn translate_class(chars: &[char]) -> Option<(String, usize)> {
+    let mut i = 1;
+    let mut out = String::from("[[");
+    if matches!(chars.get(i), Some('!') | Some('^')) {
+        out.push('^');
+        i += 1;
+    }
+    let first = i;
+
+    loop {
+        let c = *chars.get(i)?;
+        if c == ']' && i > first {
+            break;
+        }
+        if c == '-' && i > first && chars.get(i + 1).is_some_and(|&n| n != ']') {
+            out.push('-');
+        } else if c == '\\' && i + 1 < chars.len() {
+            i += 1;
+            push_class_char(&mut out, chars[i]);
+        } else {
+            push_class_char(&mut out, c);
+        }
+        i += 1;
+    }
+
+    // A set never matches the path separator.
+    out.push_str("]&&[^/]]");
+    Some((out, i + 1))
+}
+
+fn push_class_char(out: &mut String, c: char) {
+    if c.is_ascii_punctuation() {
+        out.push('\\');
+    }
+    out.push(c);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, path: &str) -> bool {
+        Glob::new(pattern).unwrap().is_match(path)
+    }
+
+    #[test]
+    fn double_star_placement() {
+        assert_eq!(translate("**/a"), "(?:.*/)?a");
+        assert_eq!(translate("a/**"), "a/.*");
+        assert_eq!(translate("a/**/b"), "a/(?:.*/)?b");
+        assert_eq!(translate("a**b"), "a[^/]*b");
+
+        assert!(matches("**/a", "a"));
+        assert!(matches("**/a", "x/y/a"));
+        assert!(matches("a/**", "a/x/y"));
+        assert!(!matches("a/**", "a"));
+        assert!(matches("a/**/b", "a/b"));
+        assert!(matches("a/**/b", "a/x/y/b"));
+        assert!(!matches("a/**/b", "a/xb"));
+        assert!(matches("a**b", "axyb"));
+        assert!(!matches("a**b", "ax/yb"));
+    }
+
+    #[test]
+    fn wildcards_stop_at_slashes() {
+        assert!(matches("*.rs", "main.rs"));
+        assert!(!matches("*.rs", "src/main.rs"));
+        assert!(matches("src/?.rs", "src/a.rs"));
+        assert!(!matches("?", "/"));
+    }
+
+    #[test]
+    fn classes() {
+        assert_eq!(translate_class(&['[', 'a', '-', 'c', ']']).unwrap().1, 5);
+        assert!(translate_class(&['[', 'a']).is_none());
+
+        assert!(matches("[a-c]x", "bx"));
+        assert!(!matches("[a-c]x", "dx"));
+        assert!(matches("[!a-c]x", "dx"));
+        assert!(!matches("[!a-c]x", "ax"));
+        assert!(matches("[^a]", "b"));
+        assert!(!matches("[!a]", "/"));
+        // `]` first in the set is literal, as is `-` at either end.
+        assert!(matches("[]]", "]"));
+        assert!(matches("[a-]", "-"));
+        // An unclosed `[` is literal.
+        assert!(matches("[a", "[a"));
+    }
+
+    #[test]
+    fn escapes() {
+        assert!(matches(r"\*", "*"));
+        assert!(!matches(r"\*", "a"));
+        assert!(matches(r"[\]]", "]"));
+        assert!(matches("a.b", "a.b"));
+        assert!(!matches("a.b", "axb"));
+    }
+}
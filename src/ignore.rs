@@ -0,0 +1,360 @@
+//! `.gitignore`-style ignore files.
+//!
+//! Inside a git work tree the walk honours, from lowest to highest
+//! precedence: the global `core.excludesFile`, `.git/info/exclude`, and the
+//! `.gitignore` files of every directory from the top of the work tree down.
+//! `.ignore` and `.dirrignore` files are honoured everywhere and take
+//! precedence over a `.gitignore` in the same directory. As in git, the last
+//! matching rule wins and a `!` rule re-includes what an earlier one ignored.
+
+use crate::glob::Glob;
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+/// Per-directory ignore files that are only read inside git work trees.
+const GIT_IGNORE_FILES: &[&str] = &[".gitignore"];
+/// Per-directory ignore files that are always read, in increasing precedence.
+const IGNORE_FILES: &[&str] = &[".ignore", ".dirrignore"];
+
+#[derive(Debug)]
+struct Rule {
+    glob: Glob,
+    negated: bool,
+    dir_only: bool,
+    /// Whether the pattern is matched against the whole relative path rather
+    /// than just the file name.
+    anchored: bool,
+}
+
+/// The rules from one ignore file.
+#[derive(Debug)]
+pub(crate) struct IgnoreFile {
+    /// The directory, as a walked path, that patterns are relative to.
+    dir: PathBuf,
+    /// Prepended to paths below `dir`, for files that live above the root
+    /// the walk started from.
+    prefix: String,
+    rules: Vec<Rule>,
+}
+
+impl IgnoreFile {
+    fn parse(contents: &str, dir: &Path, prefix: String) -> IgnoreFile {
+        let rules = contents.lines().filter_map(parse_rule).collect();
+        IgnoreFile {
+            dir: dir.to_path_buf(),
+            prefix,
+            rules,
+        }
+    }
+
+    fn load(file: &Path, dir: &Path, prefix: String) -> Option<IgnoreFile> {
+        let contents = fs::read_to_string(file).ok()?;
+        let ignore = IgnoreFile::parse(&contents, dir, prefix);
+        (!ignore.rules.is_empty()).then_some(ignore)
+    }
+
+    /// `Some(true)` if the last rule matching `path` ignores it, `Some(false)`
+    /// if it re-includes it, and `None` if no rule matches.
+    fn matched(&self, path: &Path, is_dir: bool) -> Option<bool> {
+        let relative = path.strip_prefix(&self.dir).ok()?;
+        let mut relative_str = self.prefix.clone();
+        for (i, component) in relative.components().enumerate() {
+            if i > 0 {
+                relative_str.push('/');
+            }
+            relative_str.push_str(&component.as_os_str().to_string_lossy());
+        }
+        let name = relative_str.rsplit('/').next().unwrap_or_default();
+
+        self.rules
+            .iter()
+            .rev()
+            .filter(|rule| is_dir || !rule.dir_only)
+            .find(|rule| {
+                if rule.anchored {
+                    rule.glob.is_match(&relative_str)
+                } else {
+                    rule.glob.is_match(name)
+                }
+            })
+            .map(|rule| !rule.negated)
+    }
+}
+
+fn parse_rule(line: &str) -> Option<Rule> {
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+
+    // Trailing spaces are dropped unless escaped with a backslash.
+    let mut pattern = line.trim_end_matches(' ');
+    if pattern.ends_with('\\') && pattern.len() < line.len() {
+        pattern = &line[..pattern.len() + 1];
+    }
+
+    let negated = pattern.starts_with('!');
+    if negated {
+        pattern = &pattern[1..];
+    }
+    let dir_only = pattern.ends_with('/') && !pattern.ends_with("\\/");
+    if dir_only {
+        pattern = &pattern[..pattern.len() - 1];
+    }
+    if pattern.is_empty() {
+        return None;
+    }
+
+    let anchored = pattern.contains('/');
+    let pattern = pattern.strip_prefix('/').unwrap_or(pattern);
+    let glob = Glob::new(pattern).ok()?;
+
+    Some(Rule {
+        glob,
+        negated,
+        dir_only,
+        anchored,
+    })
+}
+
+/// Decides whether a path is ignored given every ignore file that applies to
+/// it, ordered from lowest to highest precedence.
+pub(crate) fn is_ignored<'a>(
+    files: impl IntoIterator<Item = &'a IgnoreFile>,
+    path: &Path,
+    is_dir: bool,
+) -> bool {
+    files
+        .into_iter()
+        .filter_map(|file| file.matched(path, is_dir))
+        .last()
+        .unwrap_or(false)
+}
+
+/// Loads the ignore files found directly in `dir`.
+pub(crate) fn dir_ignores(dir: &Path, in_git: bool) -> Vec<IgnoreFile> {
+    let git_files: &[&str] = if in_git { GIT_IGNORE_FILES } else { &[] };
+    git_files
+        .iter()
+        .chain(IGNORE_FILES)
+        .filter_map(|name| IgnoreFile::load(&dir.join(name), dir, String::new()))
+        .collect()
+}
+
+/// The ignore files that apply to a walk starting at `root` before any of
+/// the root's own: git's global and repository excludes, and the ignore files
+/// of the directories between the top of the work tree and `root`.
+///
+/// Also returns whether `root` is inside a git work tree.
+pub(crate) fn root_ignores(root: &Path) -> (bool, Vec<IgnoreFile>) {
+    let Ok(absolute) = fs::canonicalize(root) else {
+        return (false, Vec::new());
+    };
+    let work_tree = absolute
+        .ancestors()
+        .find(|dir| dir.join(".git").exists())
+        .map(Path::to_path_buf);
+
+    let mut files = Vec::new();
+    let top = match &work_tree {
+        Some(top) => {
+            let prefix = relative_prefix(top, &absolute);
+            if let Some(global) = global_excludes_file(top) {
+                files.extend(IgnoreFile::load(&global, root, prefix.clone()));
+            }
+            if let Some(git_dir) = git_dir(top) {
+                let exclude = git_dir.join("info").join("exclude");
+                files.extend(IgnoreFile::load(&exclude, root, prefix));
+            }
+            top.clone()
+        }
+        None => absolute.clone(),
+    };
+
+    // Ignore files of the directories above the root, outermost first.
+    let mut between: Vec<&Path> = absolute
+        .ancestors()
+        .skip(1)
+        .take_while(|dir| dir.starts_with(&top))
+        .collect();
+    between.reverse();
+    for dir in between {
+        let prefix = relative_prefix(dir, &absolute);
+        for mut file in dir_ignores(dir, work_tree.is_some()) {
+            file.dir = root.to_path_buf();
+            file.prefix = prefix.clone();
+            files.push(file);
+        }
+    }
+
+    (work_tree.is_some(), files)
+}
+
+/// The path of `root` relative to `dir`, with a trailing `/`, or an empty
+/// string if they are the same directory.
+fn relative_prefix(dir: &Path, root: &Path) -> String {
+    let relative = root.strip_prefix(dir).unwrap_or(root);
+    let mut prefix = String::new();
+    for component in relative.components() {
+        prefix.push_str(&component.as_os_str().to_string_lossy());
+        prefix.push('/');
+    }
+    prefix
+}
+
+/// The repository directory for the work tree at `top`, following the
+/// `gitdir:` indirection used by linked work trees and submodules.
+fn git_dir(top: &Path) -> Option<PathBuf> {
+    let dot_git = top.join(".git");
+    if dot_git.is_dir() {
+        return Some(dot_git);
+    }
+    let contents = fs::read_to_string(&dot_git).ok()?;
+    let target = contents.trim().strip_prefix("gitdir:")?.trim();
+    Some(top.join(target))
+}
+
+/// Finds `core.excludesFile` in the repository, global and XDG git config,
+/// falling back to git's default of `$XDG_CONFIG_HOME/git/ignore`.
+fn global_excludes_file(top: &Path) -> Option<PathBuf> {
+    let home = env::var_os("HOME").map(PathBuf::from);
+    let xdg_config = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| home.as_ref().map(|h| h.join(".config")));
+
+    // Later files override earlier ones, as in git.
+    let mut configs = Vec::new();
+    if let Some(xdg) = &xdg_config {
+        configs.push(xdg.join("git").join("config"));
+    }
+    if let Some(home) = &home {
+        configs.push(home.join(".gitconfig"));
+    }
+    if let Some(git_dir) = git_dir(top) {
+        configs.push(git_dir.join("config"));
+    }
+
+    let configured = configs
+        .iter()
+        .rev()
+        .filter_map(|config| fs::read_to_string(config).ok())
+        .find_map(|contents| core_excludes_file(&contents));
+
+    match configured {
+        Some(path) => match (path.strip_prefix("~/"), &home) {
+            (Some(rest), Some(home)) => Some(home.join(rest)),
+            _ => Some(PathBuf::from(path)),
+        },
+        None => xdg_config.map(|xdg| xdg.join("git").join("ignore")),
+    }
+}
+
+/// Reads `excludesFile` from the `[core]` section of a git config file.
+fn core_excludes_file(contents: &str) -> Option<String> {
+    let mut in_core = false;
+    let mut value = None;
+    for line in contents.lines() {
+        let line = line.trim();
+        if line.starts_with('[') {
+            in_core = line.trim_matches(|c| c == '[' || c == ']').trim() == "core";
+        } else if in_core {
+            if let Some((key, val)) = line.split_once('=') {
+                if key.trim().eq_ignore_ascii_case("excludesfile") {
+                    value = Some(val.trim().trim_matches('"').to_string());
+                }
+            }
+        }
+    }
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(contents: &str) -> IgnoreFile {
+        IgnoreFile::parse(contents, Path::new("root"), String::new())
+    }
+
+    fn matched(file: &IgnoreFile, path: &str, is_dir: bool) -> Option<bool> {
+        file.matched(&Path::new("root").join(path), is_dir)
+    }
+
+    #[test]
+    fn parse_rule_syntax() {
+        assert!(parse_rule("").is_none());
+        assert!(parse_rule("# comment").is_none());
+        assert!(parse_rule("!").is_none());
+        assert!(parse_rule("/").is_none());
+
+        let rule = parse_rule("!build/").unwrap();
+        assert!(rule.negated && rule.dir_only && !rule.anchored);
+        assert_eq!(rule.glob.as_str(), "build");
+
+        let rule = parse_rule("/target").unwrap();
+        assert!(rule.anchored && !rule.dir_only);
+        assert_eq!(rule.glob.as_str(), "target");
+
+        assert!(parse_rule("docs/*.md").unwrap().anchored);
+        assert!(!parse_rule(r"\!important").unwrap().negated);
+    }
+
+    #[test]
+    fn trailing_spaces() {
+        assert_eq!(parse_rule("a  ").unwrap().glob.as_str(), "a");
+        assert_eq!(parse_rule(r"a\ ").unwrap().glob.as_str(), r"a\ ");
+        assert_eq!(parse_rule(r"a\  ").unwrap().glob.as_str(), r"a\ ");
+        assert_eq!(matched(&file(r"a\ "), "a ", false), Some(true));
+    }
+
+    #[test]
+    fn anchoring() {
+        let ignore = file("/top\nname\nsrc/gen");
+        assert_eq!(matched(&ignore, "top", false), Some(true));
+        assert_eq!(matched(&ignore, "sub/top", false), None);
+        assert_eq!(matched(&ignore, "name", false), Some(true));
+        assert_eq!(matched(&ignore, "sub/deep/name", false), Some(true));
+        assert_eq!(matched(&ignore, "src/gen", true), Some(true));
+        assert_eq!(matched(&ignore, "lib/src/gen", true), None);
+    }
+
+    #[test]
+    fn prefix_for_files_above_the_root() {
+        let ignore = IgnoreFile::parse("/sub/a", Path::new("root"), String::from("sub/"));
+        assert_eq!(ignore.matched(Path::new("root/a"), false), Some(true));
+        assert_eq!(ignore.matched(Path::new("root/b"), false), None);
+    }
+
+    #[test]
+    fn dir_only_rules() {
+        let ignore = file("out/");
+        assert_eq!(matched(&ignore, "out", true), Some(true));
+        assert_eq!(matched(&ignore, "out", false), None);
+    }
+
+    #[test]
+    fn last_matching_rule_wins() {
+        let ignore = file("*.log\n!keep.log");
+        assert_eq!(matched(&ignore, "a.log", false), Some(true));
+        assert_eq!(matched(&ignore, "keep.log", false), Some(false));
+
+        let ignore = file("!keep.log\n*.log");
+        assert_eq!(matched(&ignore, "keep.log", false), Some(true));
+    }
+
+    #[test]
+    fn later_files_take_precedence() {
+        let gitignore = file("*.log");
+        let dirrignore = file("!keep.log");
+        let files = [gitignore, dirrignore];
+        assert!(is_ignored(&files, Path::new("root/a.log"), false));
+        assert!(!is_ignored(&files, Path::new("root/keep.log"), false));
+        assert!(!is_ignored(files.iter().rev(), Path::new("root/x"), false));
+        assert!(is_ignored(
+            files.iter().rev(),
+            Path::new("root/keep.log"),
+            false
+        ));
+    }
+}
@@ -0,0 +1,197 @@
+//! JSON output for `--format json` and `--format ndjson`.
+//!
+//! For `json`, the document is an array with one object per root. Every node has the
+//! same shape:
+//!
+//! ```text
+//! {
+//!   "name": string,          file name (the path as given for roots)
+//!   "type": string,          "file", "directory", "symlink" or "other"
+//!   "size": number | null,   size in bytes as reported by the filesystem
+//!   "total": number,         --du only: the size of everything counted beneath
+//!   "modified": number | null,  seconds since the Unix epoch
+//!   "error": string | null,  why the entry or its contents could not be read
+//!   "target": string,        symlinks only: where the link points
+//!   "broken": true,          symlinks only, when the target does not exist
+//!   "cycle": true,           directories skipped because they loop back to an ancestor
+//!   "hidden": number,        directories cut off by --depth only: entries not listed
+//!   "children": [node, ...]  directories only, omitted when cut off by --depth
+//! }
+//! ```
+//!
+//! For `ndjson`, each entry is written on its own line as soon as the walk
+//! reaches it, starting with the root at depth 0:
+//!
+//! ```text
+//! {"path": string, "depth": number, "kind": string, "size": number | null,
+//!  "mtime": number | null, "target": string | null, "error": string | null}
+//! ```
+//!
+//! With `--du`, each record also has a `"total"` after `"size"`, and records
+//! only start once a root has been read in full.
+//!
+//! `kind`, `size`, `total` and `mtime` have the same meaning as `type`,
+//! `size`, `total` and `modified` above; `path` includes the root as given on the command line.
+
+use dirr::{Entry, Walker};
+use std::{
+    fmt::Write,
+    fs::Metadata,
+    io::{self, Write as _},
+    time::SystemTime,
+};
+
+/// Returns `s` as a quoted JSON string.
+fn quote(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn file_type(metadata: Option<&Metadata>) -> &'static str {
+    match metadata.map(Metadata::file_type) {
+        Some(t) if t.is_symlink() => "symlink",
+        Some(t) if t.is_dir() => "directory",
+        Some(t) if t.is_file() => "file",
+        _ => "other",
+    }
+}
+
+fn size(metadata: Option<&Metadata>) -> String {
+    metadata.map_or_else(|| String::from("null"), |m| m.len().to_string())
+}
+
+fn modified(metadata: Option<&Metadata>) -> String {
+    metadata
+        .and_then(|m| m.modified().ok())
+        .and_then(|t| t.duration_since(SystemTime::UNIX_EPOCH).ok())
+        .map_or_else(|| String::from("null"), |d| d.as_secs().to_string())
+}
+
+fn target(entry: &Entry) -> String {
+    entry
+        .link_target()
+        .map_or_else(|| String::from("null"), |t| quote(&t.to_string_lossy()))
+}
+
+fn error(entry: &Entry) -> String {
+    entry
+        .error()
+        .map_or_else(|| String::from("null"), |e| quote(&e.to_string()))
+}
+
+/// Prints the entries of one or more walks as one JSON document. Each root
+/// (depth 0) starts a new top-level node.
+pub fn print_trees(entries: &[Entry]) -> io::Result<()> {
+    let mut out = String::from("[");
+    write_nodes(&mut out, entries, 1);
+    out.push_str(if entries.is_empty() { "]" } else { "\n]" });
+    writeln!(io::stdout().lock(), "{}", out)
+}
+
+/// Prints one NDJSON record per entry of `walker` as soon as it is reached,
+/// adding a summary line to `errors` for each entry that could not be read.
+pub fn stream_tree(walker: Walker, errors: &mut Vec<String>) -> io::Result<()> {
+    let mut stdout = io::stdout().lock();
+    for entry in walker {
+        let entry = entry?;
+        errors.extend(crate::error_line(&entry));
+        writeln!(stdout, "{}", record(&entry))?;
+    }
+    Ok(())
+}
+
+fn record(entry: &Entry) -> String {
+    let total = entry
+        .total_size()
+        .map(|total| format!(", \"total\": {}", total))
+        .unwrap_or_default();
+    format!(
+        "{{\"path\": {}, \"depth\": {}, \"kind\": {}, \"size\": {}{}, \"mtime\": {}, \"target\": {}, \"error\": {}}}",
+        quote(&entry.path().to_string_lossy()),
+        entry.depth(),
+        quote(file_type(entry.metadata())),
+        size(entry.metadata()),
+        total,
+        modified(entry.metadata()),
+        target(entry),
+        error(entry)
+    )
+}
+
+/// Writes the nodes at the depth of `entries[0]` as a comma-separated list.
+/// Each node's own subtree follows it directly in `entries`.
+fn write_nodes(out: &mut String, entries: &[Entry], level: usize) {
+    let Some(depth) = entries.first().map(Entry::depth) else {
+        return;
+    };
+    let indent = "  ".repeat(level);
+
+    let mut i = 0;
+    while i < entries.len() {
+        let end = entries[i + 1..]
+            .iter()
+            .position(|e| e.depth() <= depth)
+            .map_or(entries.len(), |p| i + 1 + p);
+
+        if i > 0 {
+            out.push(',');
+        }
+        let _ = write!(out, "\n{}", indent);
+        write_node(out, &entries[i], &entries[i + 1..end], level);
+        i = end;
+    }
+}
+
+fn write_node(out: &mut String, entry: &Entry, subtree: &[Entry], level: usize) {
+    let indent = "  ".repeat(level + 1);
+    let metadata = entry.metadata();
+    let name = if entry.depth() == 0 {
+        entry.path().display().to_string()
+    } else {
+        entry.file_name()
+    };
+
+    out.push('{');
+    let _ = write!(out, "\n{}\"name\": {},", indent, quote(&name));
+    let _ = write!(out, "\n{}\"type\": {},", indent, quote(file_type(metadata)));
+    let _ = write!(out, "\n{}\"size\": {},", indent, size(metadata));
+    if let Some(total) = entry.total_size() {
+        let _ = write!(out, "\n{}\"total\": {},", indent, total);
+    }
+    let _ = write!(out, "\n{}\"modified\": {},", indent, modified(metadata));
+    let _ = write!(out, "\n{}\"error\": {}", indent, error(entry));
+    if entry.is_symlink() {
+        let _ = write!(out, ",\n{}\"target\": {}", indent, target(entry));
+    }
+    if entry.is_broken_link() {
+        let _ = write!(out, ",\n{}\"broken\": true", indent);
+    }
+    if entry.is_cycle() {
+        let _ = write!(out, ",\n{}\"cycle\": true", indent);
+    } else if let Some(hidden) = entry.hidden() {
+        let _ = write!(out, ",\n{}\"hidden\": {}", indent, hidden);
+    } else if entry.is_dir() {
+        let _ = write!(out, ",\n{}\"children\": [", indent);
+        write_nodes(out, subtree, level + 2);
+        if !subtree.is_empty() {
+            let _ = write!(out, "\n{}", indent);
+        }
+        out.push(']');
+    }
+    let _ = write!(out, "\n{}}}", "  ".repeat(level));
+}
@@ -0,0 +1,131 @@
+//! Aligned metadata columns around the tree.
+//!
+//! Every column is filled in for all lines before anything is printed, so
+//! each one can be padded to its widest cell.
+
+/// Where the metadata columns go relative to the tree.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Placement {
+    /// In front of each line, before the tree connectors.
+    Gutter,
+    /// After each line, with the block's right edge at the terminal width.
+    Right,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+    Left,
+    Right,
+}
+
+struct Column {
+    align: Align,
+    width: usize,
+    cells: Vec<String>,
+}
+
+/// A table of metadata with one row per tree line.
+pub struct Layout {
+    placement: Placement,
+    columns: Vec<Column>,
+    /// Total width of the terminal, for [`Placement::Right`].
+    width: Option<usize>,
+}
+
+/// Space between the metadata block and the tree.
+const GAP: usize = 2;
+
+impl Layout {
+    pub fn new(placement: Placement) -> Layout {
+        let width = match placement {
+            Placement::Gutter => None,
+            Placement::Right => terminal_width(),
+        };
+        Layout {
+            placement,
+            columns: Vec::new(),
+            width,
+        }
+    }
+
+    /// Adds a column holding one cell for every line, in order.
+    pub fn push_column(&mut self, align: Align, cells: Vec<String>) {
+        let width = cells.iter().map(|c| text_width(c)).max().unwrap_or(0);
+        self.columns.push(Column {
+            align,
+            width,
+            cells,
+        });
+    }
+
+    /// Lays out every line. `lines[i]` is the tree part of row `i`.
+    pub fn render(&self, lines: &[String]) -> Vec<String> {
+        if self.columns.is_empty() {
+            return lines.to_vec();
+        }
+        let block_width =
+            self.columns.iter().map(|c| c.width).sum::<usize>() + self.columns.len() - 1;
+        // Without a terminal to fill, line the block up after the longest line.
+        let right_edge = self.width.unwrap_or_else(|| {
+            lines.iter().map(|l| text_width(l)).max().unwrap_or(0) + GAP + block_width
+        });
+
+        lines
+            .iter()
+            .enumerate()
+            .map(|(row, line)| {
+                let block = self.block(row);
+                match self.placement {
+                    Placement::Gutter => format!("{}{}{}", block, " ".repeat(GAP), line),
+                    Placement::Right => {
+                        let used = text_width(line) + block_width;
+                        let pad = right_edge.saturating_sub(used).max(GAP);
+                        format!("{}{}{}", line, " ".repeat(pad), block)
+                    }
+                }
+            })
+            .collect()
+    }
+
+    /// The cells of `row`, each padded to its column's width.
+    fn block(&self, row: usize) -> String {
+        let cells: Vec<String> = self
+            .columns
+            .iter()
+            .map(|column| {
+                let cell = column.cells.get(row).map_or("", String::as_str);
+                let pad = " ".repeat(column.width - text_width(cell));
+                match column.align {
+                    Align::Left => format!("{}{}", cell, pad),
+                    Align::Right => format!("{}{}", pad, cell),
+                }
+            })
+            .collect();
+        cells.join(" ")
+    }
+}
+
+/// Width of `s` in terminal columns, counting one per character and
+/// skipping colour escape sequences.
+fn text_width(s: &str) -> usize {
+    let mut width = 0;
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // Skip to the end of the `ESC [ ... m` sequence.
+            chars.by_ref().find(|&c| c == 'm');
+        } else {
+            width += 1;
+        }
+    }
+    width
+}
+
+/// The width of the terminal on stdout, or of `$COLUMNS` when stdout is not
+/// a terminal.
+fn terminal_width() -> Option<usize> {
+    if let Some((terminal_size::Width(width), _)) = terminal_size::terminal_size() {
+        return Some(width.into());
+    }
+    std::env::var("COLUMNS").ok()?.parse().ok()
+}
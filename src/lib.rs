@@ -0,0 +1,17 @@
+//! Directory traversal behind the `dirr` command-line tool.
+//!
+//! [`Walker`] configures a walk over one or more roots and yields [`Entry`]
+//! values lazily, in the same order `dirr` prints them.
+
+mod glob;
+mod ignore;
+mod pattern;
+mod sort;
+mod time;
+mod walk;
+
+pub use glob::Glob;
+pub use pattern::Pattern;
+pub use sort::{natural_cmp, SortKey, SortOrder};
+pub use time::TimeField;
+pub use walk::{DiskUsage, Entry, EntryKind, Walk, Walker};
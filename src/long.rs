@@ -0,0 +1,180 @@
+//! The `ls -l`-style columns shown by `--long`.
+
+use crate::layout::Align;
+use dirr::Entry;
+use std::{collections::HashMap, fs::Metadata};
+
+/// Names for user and group ids, read from `/etc/passwd` and `/etc/group`.
+///
+/// Ids without an entry there (for example users from a directory service)
+/// are shown as numbers.
+#[derive(Default)]
+pub struct Owners {
+    users: HashMap<u32, String>,
+    groups: HashMap<u32, String>,
+}
+
+impl Owners {
+    pub fn load() -> Owners {
+        Owners {
+            users: read_id_file("/etc/passwd"),
+            groups: read_id_file("/etc/group"),
+        }
+    }
+
+    fn user(&self, uid: u32) -> String {
+        self.users
+            .get(&uid)
+            .cloned()
+            .unwrap_or_else(|| uid.to_string())
+    }
+
+    fn group(&self, gid: u32) -> String {
+        self.groups
+            .get(&gid)
+            .cloned()
+            .unwrap_or_else(|| gid.to_string())
+    }
+}
+
+/// Reads the `name:password:id:...` lines of a passwd or group file.
+fn read_id_file(path: &str) -> HashMap<u32, String> {
+    let Ok(contents) = std::fs::read_to_string(path) else {
+        return HashMap::new();
+    };
+    let mut names = HashMap::new();
+    for line in contents.lines().filter(|l| !l.starts_with('#')) {
+        let mut fields = line.split(':');
+        let (Some(name), Some(_), Some(id)) = (fields.next(), fields.next(), fields.next()) else {
+            continue;
+        };
+        if let Ok(id) = id.parse() {
+            // The first entry for an id wins, as with getpwuid.
+            names.entry(id).or_insert_with(|| name.to_string());
+        }
+    }
+    names
+}
+
+pub const COLUMNS: usize = 6;
+/// How each of the `COLUMNS` is aligned: text to the left, numbers to the right.
+pub const ALIGN: [Align; COLUMNS] = [
+    Align::Left,
+    Align::Right,
+    Align::Left,
+    Align::Left,
+    Align::Right,
+    Align::Right,
+];
+
+/// Mode, hard links, owner, group, inode and device of `entry`, as text.
+pub fn columns(entry: &Entry, owners: &Owners) -> [String; COLUMNS] {
+    match entry.metadata() {
+        Some(metadata) => metadata_columns(metadata, owners),
+        // As `ls` shows entries it cannot stat.
+        None => [
+            String::from("??????????"),
+            String::from("?"),
+            String::from("?"),
+            String::from("?"),
+            String::from("?"),
+            String::from("?"),
+        ],
+    }
+}
+
+#[cfg(unix)]
+fn metadata_columns(metadata: &Metadata, owners: &Owners) -> [String; COLUMNS] {
+    use std::os::unix::fs::MetadataExt;
+    [
+        mode_string(metadata),
+        metadata.nlink().to_string(),
+        owners.user(metadata.uid()),
+        owners.group(metadata.gid()),
+        metadata.ino().to_string(),
+        metadata.dev().to_string(),
+    ]
+}
+
+#[cfg(not(unix))]
+fn metadata_columns(metadata: &Metadata, _owners: &Owners) -> [String; COLUMNS] {
+    [
+        mode_string(metadata),
+        String::from("-"),
+        String::from("-"),
+        String::from("-"),
+        String::from("-"),
+        String::from("-"),
+    ]
+}
+
+/// The file type and permission bits as `ls -l` writes them, e.g.
+/// `drwxr-xr-x` or `-rwsr-x--T`.
+#[cfg(unix)]
+fn mode_string(metadata: &Metadata) -> String {
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+
+    let file_type = metadata.file_type();
+    let type_char = if file_type.is_dir() {
+        'd'
+    } else if file_type.is_symlink() {
+        'l'
+    } else if file_type.is_block_device() {
+        'b'
+    } else if file_type.is_char_device() {
+        'c'
+    } else if file_type.is_fifo() {
+        'p'
+    } else if file_type.is_socket() {
+        's'
+    } else {
+        '-'
+    };
+
+    let mode = metadata.permissions().mode();
+    let bit = |mask: u32, c: char| if mode & mask != 0 { c } else { '-' };
+    // The execute slot also shows setuid, setgid and sticky bits: lowercase
+    // when execute is set too, uppercase when it is not.
+    let special = |exec: u32, special: u32, set: char| match (mode & exec != 0, mode & special != 0)
+    {
+        (true, true) => set,
+        (false, true) => set.to_ascii_uppercase(),
+        (true, false) => 'x',
+        (false, false) => '-',
+    };
+
+    [
+        type_char,
+        bit(0o400, 'r'),
+        bit(0o200, 'w'),
+        special(0o100, 0o4000, 's'),
+        bit(0o040, 'r'),
+        bit(0o020, 'w'),
+        special(0o010, 0o2000, 's'),
+        bit(0o004, 'r'),
+        bit(0o002, 'w'),
+        special(0o001, 0o1000, 't'),
+    ]
+    .iter()
+    .collect()
+}
+
+/// Only the read-only flag is available, so write permission is all or
+/// nothing.
+#[cfg(not(unix))]
+fn mode_string(metadata: &Metadata) -> String {
+    let file_type = metadata.file_type();
+    let type_char = if file_type.is_dir() {
+        'd'
+    } else if file_type.is_symlink() {
+        'l'
+    } else {
+        '-'
+    };
+    let perms = if metadata.permissions().readonly() {
+        "r--r--r--"
+    } else {
+        "rw-rw-rw-"
+    };
+    format!("{}{}", type_char, perms)
+}
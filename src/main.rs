@@ -1,52 +1,127 @@
-use chrono::{Duration, TimeZone, Utc};
-use regex::Regex;
+mod cli;
+mod color;
+mod json;
+mod layout;
+mod long;
+
+use chrono::{Duration, Local, TimeZone, Utc};
+use cli::Config;
+use color::Colors;
+use dirr::{Entry, Walker};
+use layout::{Align, Layout};
 use std::{
-    fs::{self, Metadata},
-    path::{Path, PathBuf},
+    fs::Metadata,
+    io::{self, ErrorKind, Write},
+    path::Path,
     time::SystemTime,
 };
 
-fn get_metadata(path: &PathBuf) -> Option<Metadata> {
-    fs::metadata(path).ok()
+/// Exit status when the listing finished but some entries could not be read.
+const EXIT_PARTIAL: i32 = 1;
+/// Exit status for usage errors and roots that could not be listed at all.
+const EXIT_FAILURE: i32 = 2;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SizeUnits {
+    /// Powers of 1024: KiB, MiB, GiB...
+    Iec,
+    /// Powers of 1000: kB, MB, GB...
+    Si,
+    /// Exact byte counts.
+    Bytes,
 }
 
-fn format_file_size(size: u64) -> String {
-    const BYTE: u64 = 1;
-    const KILOBYTE: u64 = 1024 * BYTE;
-    const MEGABYTE: u64 = 1024 * KILOBYTE;
-    const GIGABYTE: u64 = 1024 * MEGABYTE;
+/// How file sizes are printed.
+#[derive(Clone, Copy)]
+pub struct SizeFormat {
+    pub units: SizeUnits,
+    /// Digits after the decimal point for scaled sizes.
+    pub precision: usize,
+    /// Whether to group digits in threes with commas.
+    pub separators: bool,
+}
 
-    if size < KILOBYTE {
-        format!("{} B", size)
-    } else if size < MEGABYTE {
-        format!("{:.2} KB", size as f64 / KILOBYTE as f64)
-    } else if size < GIGABYTE {
-        format!("{:.2} MB", size as f64 / MEGABYTE as f64)
-    } else {
-        format!("{:.2} GB", size as f64 / GIGABYTE as f64)
+fn format_file_size(size: u64, format: SizeFormat) -> String {
+    let (base, suffixes) = match format.units {
+        SizeUnits::Iec => (1024.0, ["B", "KiB", "MiB", "GiB", "TiB", "PiB", "EiB"]),
+        SizeUnits::Si => (1000.0, ["B", "kB", "MB", "GB", "TB", "PB", "EB"]),
+        SizeUnits::Bytes => return format!("{} B", group_digits(&size.to_string(), format)),
+    };
+    if (size as f64) < base {
+        return format!("{} B", group_digits(&size.to_string(), format));
     }
+
+    let mut value = size as f64;
+    let mut unit = 0;
+    while unit + 1 < suffixes.len() && value >= base {
+        value /= base;
+        unit += 1;
+    }
+    let mut number = format!("{:.*}", format.precision, value);
+    // Rounding can carry into the next unit, e.g. 1023.999 KiB to "1024.00".
+    if unit + 1 < suffixes.len() && number.parse::<f64>().is_ok_and(|n| n >= base) {
+        unit += 1;
+        number = format!("{:.*}", format.precision, value / base);
+    }
+    format!("{} {}", group_digits(&number, format), suffixes[unit])
 }
 
-fn format_metadata(metadata: &Metadata) -> String {
-    if let Ok(modified_time) = metadata.modified() {
-        let duration_since_epoch = match modified_time.duration_since(SystemTime::UNIX_EPOCH) {
-            Ok(d) => d,
-            Err(_) => return String::from(" (Unable to fetch time before UNIX_EPOCH)"),
-        };
-        let file_size = metadata.len();
+/// Inserts thousands separators into the integer part of `number` if `format`
+/// asks for them.
+fn group_digits(number: &str, format: SizeFormat) -> String {
+    if !format.separators {
+        return number.to_string();
+    }
+    let (integer, fraction) = match number.find('.') {
+        Some(dot) => number.split_at(dot),
+        None => (number, ""),
+    };
+    let mut out = String::new();
+    for (i, c) in integer.chars().enumerate() {
+        if i > 0 && (integer.len() - i) % 3 == 0 {
+            out.push(',');
+        }
+        out.push(c);
+    }
+    out.push_str(fraction);
+    out
+}
 
-        let size_str = format_file_size(file_size);
-        let time_str = format_time(duration_since_epoch);
+#[derive(Clone, PartialEq, Eq)]
+pub enum TimeStyle {
+    /// "3 days ago".
+    Relative,
+    /// RFC 3339 to the second: "2024-05-01T14:03:09+02:00".
+    Iso,
+    /// Nanoseconds and offset: "2024-05-01 14:03:09.123456789 +0200".
+    Full,
+    /// A user-supplied `strftime` format.
+    Custom(String),
+}
 
-        format!(" ({} modified {})", size_str, time_str)
-    } else {
-        String::from(" (Unable to fetch metadata)")
-    }
+/// The time chosen by `--time-field`, labelled with what it records, e.g.
+/// "modified 3 days ago".
+fn format_timestamp(metadata: &Metadata, config: &Config) -> String {
+    let verb = config.time_field.verb();
+    let Some(time) = config.time_field.of(metadata) else {
+        // Birth times in particular are missing on many filesystems.
+        return format!("{} time unavailable", verb);
+    };
+    let time = match time.duration_since(SystemTime::UNIX_EPOCH) {
+        Ok(duration_since_epoch) => {
+            format_time(duration_since_epoch, &config.time_style, config.utc)
+        }
+        Err(_) => String::from("before 1970"),
+    };
+    format!("{} {}", verb, time)
 }
 
-fn format_time(duration_since_epoch: std::time::Duration) -> String {
+fn format_time(duration_since_epoch: std::time::Duration, style: &TimeStyle, utc: bool) -> String {
     let now = Utc::now();
-    let timestamp_result = Utc.timestamp_opt(duration_since_epoch.as_secs() as i64, 0);
+    let timestamp_result = Utc.timestamp_opt(
+        duration_since_epoch.as_secs() as i64,
+        duration_since_epoch.subsec_nanos(),
+    );
 
     let file_time = match timestamp_result {
         chrono::LocalResult::Single(dt) => dt,
@@ -56,6 +131,20 @@ fn format_time(duration_since_epoch: std::time::Duration) -> String {
         }
     };
 
+    let format = match style {
+        TimeStyle::Relative => None,
+        TimeStyle::Iso => Some("%Y-%m-%dT%H:%M:%S%:z"),
+        TimeStyle::Full => Some("%Y-%m-%d %H:%M:%S%.9f %z"),
+        TimeStyle::Custom(format) => Some(format.as_str()),
+    };
+    if let Some(format) = format {
+        return if utc {
+            file_time.format(format).to_string()
+        } else {
+            file_time.with_timezone(&Local).format(format).to_string()
+        };
+    }
+
     let elapsed = now - file_time;
 
     if elapsed < Duration::minutes(1) {
@@ -69,106 +158,635 @@ fn format_time(duration_since_epoch: std::time::Duration) -> String {
     }
 }
 
-fn print_tree(paths: &[PathBuf], root: &Path, show_meta: bool) {
-    for path in paths {
-        if let Ok(display_path) = path.strip_prefix(root) {
-            let depth = display_path.components().count();
-            let prefix = "|   ".repeat(depth - 1);
-            let meta_info = if show_meta {
-                if let Some(metadata) = get_metadata(path) {
-                    format_metadata(&metadata)
-                } else {
-                    String::from(" (Error fetching metadata)")
-                }
-            } else {
-                String::new()
-            };
-            println!("{}|--{}{}", prefix, display_path.display(), meta_info);
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Charset {
+    Utf8,
+    Ascii,
+}
+
+impl Charset {
+    /// Returns the (branch, last branch, continuation, gap) pieces used to draw the tree.
+    fn connectors(self) -> (&'static str, &'static str, &'static str, &'static str) {
+        match self {
+            Charset::Utf8 => ("├── ", "└── ", "│   ", "    "),
+            Charset::Ascii => ("+-- ", "\\-- ", "|   ", "    "),
         }
     }
 }
 
-fn generate_tree<P: AsRef<Path>>(
-    path: P,
-    exclude: &[Regex],
-) -> Result<Vec<PathBuf>, std::io::Error> {
-    let mut results = Vec::new();
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Tree,
+    Json,
+    Ndjson,
+}
+
+/// How many directories and other entries were listed, and the total size of
+/// the other entries.
+#[derive(Clone, Copy, Default)]
+struct Counts {
+    dirs: usize,
+    files: usize,
+    bytes: u64,
+}
 
-    if let Ok(entries) = fs::read_dir(&path) {
-        for entry_result in entries {
-            let entry = entry_result?;
-            let current_path = entry.path();
-            if !is_excluded(&current_path, exclude) {
-                results.push(current_path.clone());
-                if current_path.is_dir() {
-                    results.extend(generate_tree(&current_path, exclude)?);
+impl Counts {
+    fn add(&mut self, entry: &Entry) {
+        if entry.is_dir() {
+            self.dirs += 1;
+        } else {
+            self.files += 1;
+            self.bytes += entry.metadata().map_or(0, Metadata::len);
+        }
+    }
+
+    fn merge(&mut self, other: Counts) {
+        self.dirs += other.dirs;
+        self.files += other.files;
+        self.bytes += other.bytes;
+    }
+
+    /// E.g. "2 directories, 1 file".
+    fn summary(&self) -> String {
+        let plural =
+            |n: usize, one: &str, many: &str| format!("{} {}", n, if n == 1 { one } else { many });
+        format!(
+            "{}, {}",
+            plural(self.dirs, "directory", "directories"),
+            plural(self.files, "file", "files")
+        )
+    }
+}
+
+/// The entries of one root in display order, with what each one contains.
+#[derive(Default)]
+struct Tree {
+    entries: Vec<Entry>,
+    /// The lines to print, with what `--max-entries` leaves out summarised.
+    rows: Vec<Row>,
+    /// The listed direct children of `entries[i]`.
+    children: Vec<Counts>,
+    /// Everything listed below the root.
+    total: Counts,
+}
+
+/// One line of a printed tree.
+enum Row {
+    /// `tree.entries[i]`.
+    Entry(usize),
+    /// The children of a directory left out by `--max-entries`, summarised at
+    /// their depth.
+    More {
+        depth: usize,
+        count: usize,
+        bytes: u64,
+    },
+}
+
+/// Picks the rows to print, keeping the first `max` children of each
+/// directory in sort order and summarising the rest after them.
+fn limit_rows(entries: &[Entry], max: Option<usize>) -> Vec<Row> {
+    let Some(max) = max else {
+        return (0..entries.len()).map(Row::Entry).collect();
+    };
+
+    let mut rows = Vec::with_capacity(entries.len());
+    // Per depth: children shown so far, and the count and size of those left
+    // out, for the directory being listed at the depth above.
+    let mut shown: Vec<usize> = Vec::new();
+    let mut left_out: Vec<Option<(usize, u64)>> = Vec::new();
+    // Depth of the left-out child whose subtree is being skipped, and
+    // whether its descendants still need adding to the size.
+    let mut skipping: Option<(usize, bool)> = None;
+
+    // Summarises what was left out at each depth below `depth`, deepest first.
+    let close_levels = |rows: &mut Vec<Row>, left_out: &mut Vec<Option<(usize, u64)>>, depth| {
+        while left_out.len() > depth {
+            if let Some((count, bytes)) = left_out.pop().flatten() {
+                rows.push(Row::More {
+                    depth: left_out.len(),
+                    count,
+                    bytes,
+                });
+            }
+        }
+    };
+
+    for (i, entry) in entries.iter().enumerate() {
+        let depth = entry.depth();
+        if let Some((skip_depth, add_descendants)) = skipping {
+            if depth > skip_depth {
+                if add_descendants && !entry.is_dir() {
+                    if let Some((_, bytes)) = &mut left_out[skip_depth] {
+                        *bytes += entry.metadata().map_or(0, Metadata::len);
+                    }
                 }
+                continue;
+            }
+            skipping = None;
+        }
+
+        close_levels(&mut rows, &mut left_out, depth + 1);
+        shown.truncate(depth + 1);
+        shown.resize(depth + 1, 0);
+        left_out.resize(depth + 1, None);
+
+        shown[depth] += 1;
+        if depth > 0 && shown[depth] > max {
+            // With --du the total already covers everything beneath.
+            let own = entry
+                .total_size()
+                .or_else(|| entry.metadata().filter(|m| !m.is_dir()).map(Metadata::len));
+            let (count, bytes) = left_out[depth].get_or_insert((0, 0));
+            *count += 1;
+            *bytes += own.unwrap_or(0);
+            skipping = Some((depth, entry.total_size().is_none()));
+            continue;
+        }
+        rows.push(Row::Entry(i));
+    }
+    close_levels(&mut rows, &mut left_out, 0);
+    rows
+}
+
+fn print_tree(out: &mut impl Write, tree: &Tree, config: &Config) -> io::Result<()> {
+    let entries = &tree.entries;
+    let rows = &tree.rows;
+    let (branch, last_branch, continuation, gap) = config.charset.connectors();
+    // `open[d]` is true while the ancestor at depth `d + 1` still has siblings to come.
+    let mut open: Vec<bool> = Vec::new();
+    let mut root = Path::new("");
+    let mut lines = Vec::with_capacity(rows.len());
+    let colors = config.color.enabled().then(Colors::from_env);
+    let paint = |entry: &Entry, text: String| match &colors {
+        Some(colors) => colors.paint(entry, &text),
+        None => text,
+    };
+
+    for row in rows {
+        let i = match *row {
+            Row::Entry(i) => i,
+            Row::More {
+                depth,
+                count,
+                bytes,
+            } => {
+                open.truncate(depth - 1);
+                let prefix: String = open
+                    .iter()
+                    .map(|&more| if more { continuation } else { gap })
+                    .collect();
+                open.push(false);
+                let ellipsis = match config.charset {
+                    Charset::Utf8 => "…",
+                    Charset::Ascii => "...",
+                };
+                let count_format = SizeFormat {
+                    separators: true,
+                    ..config.size_format
+                };
+                lines.push(format!(
+                    "{}{}{} {} more {} ({})",
+                    prefix,
+                    last_branch,
+                    ellipsis,
+                    group_digits(&count.to_string(), count_format),
+                    if count == 1 { "entry" } else { "entries" },
+                    format_file_size(bytes, config.size_format)
+                ));
+                continue;
             }
+        };
+        let entry = &entries[i];
+        let error_info = entry
+            .error()
+            .map(|e| format!(" [{}]", describe_error(e)))
+            .unwrap_or_default();
+        if entry.depth() == 0 {
+            root = entry.path();
+            open.clear();
+            let name = paint(entry, root.display().to_string());
+            lines.push(format!("{}{}", name, error_info));
+            continue;
+        }
+        open.truncate(entry.depth() - 1);
+        let prefix: String = open
+            .iter()
+            .map(|&more| if more { continuation } else { gap })
+            .collect();
+        open.push(!entry.is_last());
+
+        let connector = if entry.is_last() { last_branch } else { branch };
+        let mut name = paint(
+            entry,
+            if config.full_path {
+                let display_path = entry.path().strip_prefix(root).unwrap_or(entry.path());
+                display_path.display().to_string()
+            } else {
+                entry.file_name()
+            },
+        );
+        if let Some(target) = entry.link_target() {
+            name.push_str(&format!(" -> {}", target.display()));
+        }
+        if entry.is_broken_link() {
+            name.push_str(" [broken link]");
+        }
+        if entry.is_cycle() {
+            name.push_str(" [recursive, not followed]");
+        }
+        let hidden_info = match entry.hidden() {
+            Some(0) | None => String::new(),
+            Some(1) => String::from(" [1 entry hidden]"),
+            Some(n) => format!(" [{} entries hidden]", n),
+        };
+        // Directories that were not read have nothing to count.
+        let counts_info = if config.dir_counts
+            && entry.is_dir()
+            && entry.hidden().is_none()
+            && !entry.is_cycle()
+            && entry.error().is_none()
+        {
+            format!(" [{}]", tree.children[i].summary())
+        } else {
+            String::new()
+        };
+        lines.push(format!(
+            "{}{}{}{}{}{}",
+            prefix, connector, name, hidden_info, counts_info, error_info
+        ));
+    }
+
+    let row_entries: Vec<Option<&Entry>> = rows
+        .iter()
+        .map(|row| match *row {
+            Row::Entry(i) => Some(&entries[i]),
+            Row::More { .. } => None,
+        })
+        .collect();
+    for line in metadata_layout(&row_entries, config).render(&lines) {
+        writeln!(out, "{}", line)?;
+    }
+    Ok(())
+}
+
+/// The metadata columns asked for on the command line: those of `--long`,
+/// then the size (with `-m` or `--du`) and the time (with `-m`). Rows without
+/// an entry are left blank.
+fn metadata_layout(entries: &[Option<&Entry>], config: &Config) -> Layout {
+    let mut layout = Layout::new(config.placement);
+
+    if config.long {
+        let owners = long::Owners::load();
+        let mut rows: Vec<_> = entries
+            .iter()
+            .map(|e| match e {
+                Some(entry) => long::columns(entry, &owners),
+                None => Default::default(),
+            })
+            .map(IntoIterator::into_iter)
+            .collect();
+        for align in long::ALIGN {
+            let cells = rows.iter_mut().filter_map(Iterator::next).collect();
+            layout.push_column(align, cells);
         }
     }
 
-    Ok(results)
+    if config.show_meta || config.disk_usage.is_some() {
+        // With --du, the total replaces the entry's own size.
+        let cells = entries
+            .iter()
+            .map(|entry| match entry {
+                Some(entry) => match entry.total_size().or(entry.metadata().map(Metadata::len)) {
+                    Some(size) => format_file_size(size, config.size_format),
+                    None => String::from("?"),
+                },
+                None => String::new(),
+            })
+            .collect();
+        layout.push_column(Align::Right, cells);
+    }
+
+    if config.show_meta {
+        let cells = entries
+            .iter()
+            .map(|entry| match entry.map(Entry::metadata) {
+                Some(Some(metadata)) => format_timestamp(metadata, config),
+                Some(None) => String::from("?"),
+                None => String::new(),
+            })
+            .collect();
+        layout.push_column(Align::Left, cells);
+    }
+
+    layout
 }
 
-fn is_excluded<P: AsRef<Path>>(path: P, exclude_patterns: &[Regex]) -> bool {
-    path.as_ref()
-        .to_string_lossy()
-        .split(std::path::MAIN_SEPARATOR)
-        .any(|comp| exclude_patterns.iter().any(|re| re.is_match(comp)))
+/// Short, lowercase description of `e` for inline display, e.g. "permission denied".
+fn describe_error(e: &io::Error) -> String {
+    let message = e.to_string();
+    let message = match message.rfind(" (os error ") {
+        Some(pos) => &message[..pos],
+        None => &message,
+    };
+    let mut chars = message.chars();
+    match chars.next() {
+        Some(first) => first.to_lowercase().chain(chars).collect(),
+        None => String::new(),
+    }
 }
 
-fn print_help() {
-    println!("dirr - A simple directory listing tool with exclusions and metadata support");
-    println!();
-    println!("Usage:");
-    println!("  dirr [FLAGS] [PATTERNS]");
-    println!();
-    println!("Flags:");
-    println!("  --help, -h      Shows this help message.");
-    println!("  --meta, -m      Shows metadata (file size and modified time) alongside the directory listing.");
-    println!("  --exclude, -x   Excludes directories that match the provided patterns. Supports regex patterns.");
-    println!();
-    println!("Examples:");
-    println!("  dirr -m -x *tmp*");
-    println!("    This will list all directories excluding those that have 'tmp' in their name and will show file metadata.");
+/// Builds the walk for one root from the command-line settings.
+fn walker(root: &Path, config: &Config) -> Walker {
+    let mut walker = Walker::new(root)
+        .sort(config.sort)
+        .follow_links(config.follow)
+        .ignore_files(config.ignore_files)
+        .skip_hidden(!config.show_hidden);
+    if let Some(mode) = config.disk_usage {
+        walker = walker.disk_usage(mode);
+    }
+    for pattern in &config.exclude {
+        walker = walker.exclude(pattern.clone());
+    }
+    for pattern in &config.include {
+        walker = walker.include(pattern.clone());
+    }
+    for &kind in &config.kinds {
+        walker = walker.kind(kind);
+    }
+    for ext in &config.extensions {
+        walker = walker.extension(ext);
+    }
+    if let Some(depth) = config.max_depth {
+        walker = walker.max_depth(depth);
+    }
+    walker
+}
+
+/// The line listed for `entry` in the error summary, if it has an error.
+fn error_line(entry: &Entry) -> Option<String> {
+    entry
+        .error()
+        .map(|e| format!("{}: {}", entry.path().display(), e))
+}
+
+/// Walks `root` and returns its entries in display order, starting with the
+/// root itself, counting what each directory contains along the way.
+fn generate_tree(root: &Path, config: &Config) -> Result<Tree, std::io::Error> {
+    let mut tree = Tree {
+        entries: walker(root, config)
+            .into_iter()
+            .collect::<io::Result<_>>()?,
+        ..Tree::default()
+    };
+    tree.rows = limit_rows(&tree.entries, config.max_entries);
+    tree.children = vec![Counts::default(); tree.entries.len()];
+    // `parents[d]` is the index of the directory being listed at depth `d`.
+    let mut parents: Vec<usize> = Vec::new();
+    for row in &tree.rows {
+        let Row::Entry(i) = *row else {
+            continue;
+        };
+        let entry = &tree.entries[i];
+        parents.truncate(entry.depth());
+        if let Some(&parent) = parents.last() {
+            tree.children[parent].add(entry);
+            tree.total.add(entry);
+        }
+        parents.push(i);
+    }
+    // With --du the root's total also covers what --depth leaves out, and
+    // directories' own blocks with --blocks, so the footer uses it instead.
+    if let Some(size) = tree.entries.first().and_then(Entry::total_size) {
+        tree.total.bytes = size;
+    }
+    Ok(tree)
+}
+
+/// Stops the run if writing to stdout failed. A closed pipe, as with
+/// `dirr | head`, ends it quietly, like the NDJSON output does.
+fn check_output(result: io::Result<()>) {
+    match result {
+        Ok(()) => {}
+        Err(e) if e.kind() == ErrorKind::BrokenPipe => std::process::exit(0),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(EXIT_FAILURE);
+        }
+    }
 }
 
 fn main() {
-    let args: Vec<String> = std::env::args().collect();
+    // `wild` expands glob arguments on Windows, where the shell does not.
+    let args: Vec<String> = wild::args().skip(1).collect();
 
-    let mut show_meta = false;
-    let mut exclude_patterns: Vec<Regex> = Vec::new();
+    let config = match Config::from_args(&args) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            eprintln!();
+            eprintln!("{}", cli::usage_hint(&cli::DIRR));
+            std::process::exit(EXIT_FAILURE);
+        }
+    };
 
-    for arg in &args[1..] {
-        match arg.as_str() {
-            "--help" | "-h" => {
-                print_help();
-                return;
+    if config.help {
+        check_output(cli::print_help(&cli::DIRR));
+        return;
+    }
+
+    let mut failed = false;
+    let mut errors = Vec::new();
+    let mut json_entries = Vec::new();
+    // What was listed across all roots, once at least one was.
+    let mut total: Option<Counts> = None;
+    // Trees are separated by a blank line, whichever roots failed before them.
+    let mut printed = false;
+    for root in &config.roots {
+        if !root.is_dir() {
+            eprintln!("Error: '{}' is not a directory.", root.display());
+            failed = true;
+            continue;
+        }
+
+        if config.format == OutputFormat::Ndjson {
+            match json::stream_tree(walker(root, &config), &mut errors) {
+                Ok(()) => {}
+                Err(e) if e.kind() == ErrorKind::BrokenPipe => return,
+                Err(e) => {
+                    eprintln!("Error: '{}': {}", root.display(), e);
+                    failed = true;
+                }
             }
-            "--meta" | "-m" => {
-                show_meta = true;
+            continue;
+        }
+
+        let tree = match generate_tree(root, &config) {
+            Ok(tree) => tree,
+            Err(e) => {
+                eprintln!("Error: '{}': {}", root.display(), e);
+                failed = true;
+                continue;
             }
-            "--exclude" | "-x" => {
-                let patterns = args
-                    .split(|s| s == "--exclude" || s == "-x")
-                    .last()
-                    .unwrap_or(&[]);
-                for pattern in patterns {
-                    if let Ok(re) = Regex::new(pattern) {
-                        exclude_patterns.push(re);
-                    } else {
-                        println!("Error: Invalid exclusion pattern '{}'.", pattern);
-                        return;
-                    }
-                }
+        };
+        errors.extend(tree.entries.iter().filter_map(error_line));
+        total.get_or_insert_default().merge(tree.total);
+        match config.format {
+            OutputFormat::Tree => {
+                let mut stdout = io::stdout().lock();
+                let separator = if printed { writeln!(stdout) } else { Ok(()) };
+                check_output(separator.and_then(|()| print_tree(&mut stdout, &tree, &config)));
+                printed = true;
             }
-            _ => {}
+            OutputFormat::Json => json_entries.extend(tree.entries),
+            OutputFormat::Ndjson => unreachable!(),
+        }
+    }
+
+    if config.format == OutputFormat::Json {
+        check_output(json::print_trees(&json_entries));
+    } else if let Some(total) = total.filter(|_| config.report) {
+        let mut stdout = io::stdout().lock();
+        check_output(writeln!(stdout).and_then(|()| {
+            writeln!(
+                stdout,
+                "{}, {}",
+                total.summary(),
+                format_file_size(total.bytes, config.size_format)
+            )
+        }));
+    }
+
+    if !errors.is_empty() {
+        eprintln!();
+        match errors.len() {
+            1 => eprintln!("1 entry could not be read:"),
+            n => eprintln!("{} entries could not be read:", n),
+        }
+        for line in &errors {
+            eprintln!("  {}", line);
+        }
+    }
+
+    if failed {
+        std::process::exit(EXIT_FAILURE);
+    } else if !errors.is_empty() {
+        std::process::exit(EXIT_PARTIAL);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, path::PathBuf};
+
+    fn iec(precision: usize) -> SizeFormat {
+        SizeFormat {
+            units: SizeUnits::Iec,
+            precision,
+            separators: false,
+        }
+    }
+
+    #[test]
+    fn file_sizes() {
+        assert_eq!(format_file_size(1023, iec(2)), "1023 B");
+        assert_eq!(format_file_size(1024, iec(2)), "1.00 KiB");
+        assert_eq!(format_file_size(1536, iec(1)), "1.5 KiB");
+        let si = SizeFormat {
+            units: SizeUnits::Si,
+            ..iec(0)
+        };
+        assert_eq!(format_file_size(1_500_000, si), "2 MB");
+        let bytes = SizeFormat {
+            units: SizeUnits::Bytes,
+            separators: true,
+            ..iec(2)
+        };
+        assert_eq!(format_file_size(1_234_567, bytes), "1,234,567 B");
+    }
+
+    #[test]
+    fn rounding_carries_into_the_next_unit() {
+        assert_eq!(format_file_size(1024 * 1024 - 1, iec(2)), "1.00 MiB");
+        assert_eq!(format_file_size(1024 * 1024 - 100, iec(0)), "1 MiB");
+        assert_eq!(format_file_size(1024 * 1024 - 100, iec(2)), "1023.90 KiB");
+    }
+
+    /// A directory under the system temp directory, removed when dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> TempDir {
+            let path = std::env::temp_dir().join(format!("dirr-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir(&path).unwrap();
+            TempDir(path)
+        }
+
+        fn file(&self, path: &str, len: usize) {
+            let path = self.0.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, vec![0; len]).unwrap();
         }
     }
 
-    let current_dir = PathBuf::from(".");
-    match generate_tree(&current_dir, &exclude_patterns) {
-        Ok(paths) => print_tree(&paths, &current_dir, show_meta),
-        Err(e) => println!("Error: {}", e),
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// The rows for a walk of `dir`, as names and `+N (bytes)` summaries.
+    fn rows(dir: &TempDir, max: usize) -> Vec<String> {
+        let entries: Vec<Entry> = Walker::new(&dir.0)
+            .into_iter()
+            .map(Result::unwrap)
+            .collect();
+        limit_rows(&entries, Some(max))
+            .into_iter()
+            .map(|row| match row {
+                Row::Entry(0) => String::from("."),
+                Row::Entry(i) => entries[i].file_name(),
+                Row::More {
+                    depth,
+                    count,
+                    bytes,
+                } => format!("{}: +{} ({})", depth, count, bytes),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn limit_rows_summarises_each_directory() {
+        let dir = TempDir::new("limit-rows");
+        dir.file("a/x1", 10);
+        dir.file("a/x2", 20);
+        dir.file("a/x3", 30);
+        dir.file("b", 5);
+        dir.file("c", 7);
+        dir.file("d/y", 100);
+
+        assert_eq!(
+            rows(&dir, 2),
+            [".", "a", "x1", "x2", "2: +1 (30)", "b", "1: +2 (107)"]
+        );
+        assert_eq!(rows(&dir, 1), [".", "a", "x1", "2: +2 (50)", "1: +3 (112)"]);
+        assert_eq!(
+            rows(&dir, 4),
+            [".", "a", "x1", "x2", "x3", "b", "c", "d", "y"]
+        );
+    }
+
+    #[test]
+    fn limit_rows_without_a_limit() {
+        let dir = TempDir::new("no-limit");
+        dir.file("a", 1);
+        let entries: Vec<Entry> = Walker::new(&dir.0)
+            .into_iter()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(limit_rows(&entries, None).len(), 2);
     }
 }
@@ -0,0 +1,260 @@
+//! Patterns that select entries by name or by path.
+
+use crate::glob::Glob;
+use regex::Regex;
+use std::fmt;
+
+/// A glob or regular expression tested against an entry's name, or against
+/// its path relative to the root being walked.
+///
+/// Globs must match the whole name or path; regexes match if they are found
+/// anywhere in it, so use `^` and `$` to anchor them.
+///
+/// As in `.gitignore`, a glob containing a `/` is matched against the
+/// relative path (`src/generated`, `docs/**/*.png`) and a leading `/` only
+/// marks it as such. Other globs and all regexes are matched against the
+/// name alone, which excludes or includes matching entries at any depth.
+///
+/// Unlike in `.gitignore`, a trailing `/` is dropped and does not limit the
+/// glob to directories: `target/` is the same as `target`.
+#[derive(Clone, Debug)]
+pub struct Pattern {
+    matcher: Matcher,
+    anchored: bool,
+}
+
+#[derive(Clone, Debug)]
+enum Matcher {
+    Glob(Glob),
+    Regex(Regex),
+}
+
+impl Pattern {
+    /// Parses `pattern` as a glob, or as a regex if it starts with `re:` or
+    /// `regex` is set. A `glob:` prefix forces a glob either way.
+    ///
+    /// Before those, a `path:` prefix matches the pattern against the
+    /// relative path and a `name:` prefix against the name only, overriding
+    /// the choice made from the pattern itself.
+    pub fn parse(pattern: &str, regex: bool) -> Result<Pattern, regex::Error> {
+        let (scope, pattern) = if let Some(rest) = pattern.strip_prefix("path:") {
+            (Some(true), rest)
+        } else if let Some(rest) = pattern.strip_prefix("name:") {
+            (Some(false), rest)
+        } else {
+            (None, pattern)
+        };
+
+        let parsed = if let Some(re) = pattern.strip_prefix("re:") {
+            Pattern::from(Regex::new(re)?)
+        } else if let Some(glob) = pattern.strip_prefix("glob:") {
+            Pattern::glob(glob)?
+        } else if regex {
+            Pattern::from(Regex::new(pattern)?)
+        } else {
+            Pattern::glob(pattern)?
+        };
+
+        Ok(match scope {
+            Some(anchored) => parsed.anchored(anchored),
+            None => parsed,
+        })
+    }
+
+    fn glob(pattern: &str) -> Result<Pattern, regex::Error> {
+        let (glob, anchored) = split_slashes(pattern);
+        Ok(Pattern {
+            matcher: Matcher::Glob(Glob::new(glob)?),
+            anchored,
+        })
+    }
+
+    /// Sets whether the pattern is matched against the relative path rather
+    /// than the name.
+    pub fn anchored(mut self, anchored: bool) -> Pattern {
+        self.anchored = anchored;
+        self
+    }
+
+    pub fn is_anchored(&self) -> bool {
+        self.anchored
+    }
+
+    /// Tests `text` against the pattern as it is, ignoring its scope.
+    pub fn is_match(&self, text: &str) -> bool {
+        match &self.matcher {
+            Matcher::Glob(glob) => glob.is_match(text),
+            Matcher::Regex(regex) => regex.is_match(text),
+        }
+    }
+
+    /// Tests an entry, given its `/`-separated path relative to the root.
+    pub fn matches_path(&self, relative: &str) -> bool {
+        if self.anchored {
+            self.is_match(relative)
+        } else {
+            self.is_match(relative.rsplit('/').next().unwrap_or_default())
+        }
+    }
+}
+
+/// Strips a leading and a trailing `/` from a glob, and says whether what is
+/// left should be matched against the relative path.
+fn split_slashes(pattern: &str) -> (&str, bool) {
+    let pattern = match pattern.strip_suffix('/') {
+        Some(rest) if !rest.is_empty() => rest,
+        _ => pattern,
+    };
+    let anchored = pattern.contains('/');
+    (pattern.strip_prefix('/').unwrap_or(pattern), anchored)
+}
+
+impl From<Glob> for Pattern {
+    fn from(glob: Glob) -> Pattern {
+        let (pattern, anchored) = split_slashes(glob.as_str());
+        // Dropping a literal `/` at either end leaves a valid glob.
+        let glob = if pattern == glob.as_str() {
+            glob
+        } else {
+            Glob::new(pattern).unwrap_or(glob)
+        };
+        Pattern {
+            matcher: Matcher::Glob(glob),
+            anchored,
+        }
+    }
+}
+
+impl From<Regex> for Pattern {
+    fn from(regex: Regex) -> Pattern {
+        Pattern {
+            matcher: Matcher::Regex(regex),
+            anchored: false,
+        }
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.matcher {
+            Matcher::Glob(glob) => {
+                match (self.anchored, glob.as_str().contains('/')) {
+                    (true, false) => f.write_str("/")?,
+                    (false, true) => f.write_str("name:")?,
+                    _ => {}
+                }
+                write!(f, "{}", glob)
+            }
+            Matcher::Regex(regex) => {
+                if self.anchored {
+                    f.write_str("path:")?;
+                }
+                write!(f, "re:{}", regex)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn glob(pattern: &str) -> Pattern {
+        Pattern::parse(pattern, false).unwrap()
+    }
+
+    #[test]
+    fn slash_anchors_to_the_root() {
+        let pattern = glob("/src/generated");
+        assert!(pattern.is_anchored());
+        assert!(pattern.matches_path("src/generated"));
+        assert!(!pattern.matches_path("lib/generated"));
+        assert!(!pattern.matches_path("generated"));
+
+        let pattern = glob("src/generated");
+        assert!(pattern.matches_path("src/generated"));
+        assert!(!pattern.matches_path("lib/src/generated"));
+
+        let pattern = glob("/target");
+        assert!(pattern.matches_path("target"));
+        assert!(!pattern.matches_path("src/target"));
+    }
+
+    #[test]
+    fn names_match_at_any_depth() {
+        let pattern = glob("generated");
+        assert!(!pattern.is_anchored());
+        assert!(pattern.matches_path("generated"));
+        assert!(pattern.matches_path("src/deep/generated"));
+        assert!(!pattern.matches_path("generated/file"));
+    }
+
+    #[test]
+    fn double_star_paths() {
+        let pattern = glob("docs/**/*.png");
+        assert!(pattern.matches_path("docs/a.png"));
+        assert!(pattern.matches_path("docs/img/x/a.png"));
+        assert!(!pattern.matches_path("src/docs/a.png"));
+        assert!(!pattern.matches_path("docs/a.jpg"));
+    }
+
+    #[test]
+    fn trailing_slash_is_dropped() {
+        for pattern in [
+            glob("target/"),
+            Pattern::from(Glob::new("target/").unwrap()),
+        ] {
+            assert!(!pattern.is_anchored());
+            assert!(pattern.matches_path("target"));
+            assert!(pattern.matches_path("src/target"));
+            assert_eq!(pattern.to_string(), "target");
+        }
+
+        let pattern = glob("/src/target/");
+        assert!(pattern.matches_path("src/target"));
+        assert!(!pattern.matches_path("target"));
+    }
+
+    #[test]
+    fn from_glob_strips_a_leading_slash() {
+        let pattern = Pattern::from(Glob::new("/src").unwrap());
+        assert!(pattern.is_anchored());
+        assert!(pattern.matches_path("src"));
+        assert!(!pattern.matches_path("lib/src"));
+        assert_eq!(pattern.to_string(), "/src");
+
+        let pattern = Pattern::from(Glob::new("src/*").unwrap());
+        assert!(pattern.is_anchored());
+        assert!(pattern.matches_path("src/main.rs"));
+    }
+
+    #[test]
+    fn scope_prefixes() {
+        let pattern = glob("name:src/x");
+        assert!(!pattern.is_anchored());
+        assert!(!pattern.matches_path("src/x"));
+        assert_eq!(pattern.to_string(), "name:src/x");
+
+        let pattern = glob("path:re:^build/");
+        assert!(pattern.is_anchored());
+        assert!(pattern.matches_path("build/out.o"));
+        assert!(!pattern.matches_path("src/build/out.o"));
+        assert_eq!(pattern.to_string(), "path:re:^build/");
+
+        let pattern = glob("path:target");
+        assert!(pattern.matches_path("target"));
+        assert!(!pattern.matches_path("src/target"));
+    }
+
+    #[test]
+    fn regexes_match_names_anywhere() {
+        let pattern = Pattern::parse("tmp", true).unwrap();
+        assert!(!pattern.is_anchored());
+        assert!(pattern.matches_path("src/my_tmp_file"));
+        assert!(!pattern.matches_path("tmp/file"));
+        assert!(Pattern::parse("glob:*.rs", true)
+            .unwrap()
+            .matches_path("a/b.rs"));
+        assert!(Pattern::parse("re:(", false).is_err());
+    }
+}
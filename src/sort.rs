@@ -0,0 +1,178 @@
+//! Ordering of sibling entries.
+
+use crate::time::TimeField;
+use std::{cmp::Ordering, fs::Metadata, path::Path};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SortKey {
+    /// Natural name order, so `file2` sorts before `file10`.
+    #[default]
+    Name,
+    /// Largest first. With disk usage on, directories sort by their totals.
+    Size,
+    /// Most recently modified first.
+    Mtime,
+    /// Most recently accessed first.
+    Atime,
+    /// Most recently created first. Entries without a creation time last.
+    Btime,
+    /// Most recently changed first, see [`TimeField::Ctime`].
+    Ctime,
+    /// By extension, then by name.
+    Ext,
+    /// Whatever order the filesystem returns.
+    None,
+}
+
+impl From<TimeField> for SortKey {
+    fn from(field: TimeField) -> SortKey {
+        match field {
+            TimeField::Mtime => SortKey::Mtime,
+            TimeField::Atime => SortKey::Atime,
+            TimeField::Btime => SortKey::Btime,
+            TimeField::Ctime => SortKey::Ctime,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SortOrder {
+    pub key: SortKey,
+    pub reverse: bool,
+    pub dirs_first: bool,
+}
+
+/// What `sort_entries` needs to know about each sibling.
+pub(crate) trait SortItem {
+    fn path(&self) -> &Path;
+    fn metadata(&self) -> Option<&Metadata>;
+
+    /// The size of everything beneath the item, once it is known.
+    fn total_size(&self) -> Option<u64> {
+        None
+    }
+}
+
+pub(crate) fn sort_entries<T: SortItem>(entries: &mut [T], order: SortOrder) {
+    if order.key == SortKey::None && !order.dirs_first {
+        return;
+    }
+
+    let name = |path: &Path| {
+        path.file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    };
+    let ext = |path: &Path| {
+        path.extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default()
+    };
+    let size = |item: &T| {
+        item.total_size()
+            .unwrap_or_else(|| item.metadata().map_or(0, Metadata::len))
+    };
+    let time = |item: &T, field: TimeField| item.metadata().and_then(|m| field.of(m));
+    let is_dir = |item: &T| item.metadata().is_some_and(Metadata::is_dir);
+    let by_name = |a: &T, b: &T| natural_cmp(&name(a.path()), &name(b.path()));
+    let by_time = |a: &T, b: &T, field: TimeField| {
+        time(b, field)
+            .cmp(&time(a, field))
+            .then_with(|| by_name(a, b))
+    };
+
+    entries.sort_by(|a, b| {
+        let by_key = match order.key {
+            SortKey::Name => by_name(a, b),
+            SortKey::Size => size(b).cmp(&size(a)).then_with(|| by_name(a, b)),
+            SortKey::Mtime => by_time(a, b, TimeField::Mtime),
+            SortKey::Atime => by_time(a, b, TimeField::Atime),
+            SortKey::Btime => by_time(a, b, TimeField::Btime),
+            SortKey::Ctime => by_time(a, b, TimeField::Ctime),
+            SortKey::Ext => ext(a.path())
+                .cmp(&ext(b.path()))
+                .then_with(|| by_name(a, b)),
+            SortKey::None => Ordering::Equal,
+        };
+        let by_key = if order.reverse {
+            by_key.reverse()
+        } else {
+            by_key
+        };
+        if order.dirs_first {
+            is_dir(b).cmp(&is_dir(a)).then(by_key)
+        } else {
+            by_key
+        }
+    });
+}
+
+/// Compares names case-insensitively, treating runs of digits as numbers.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a_chars = a.chars().peekable();
+    let mut b_chars = b.chars().peekable();
+
+    loop {
+        match (a_chars.peek().copied(), b_chars.peek().copied()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let take_number = |chars: &mut std::iter::Peekable<std::str::Chars>| {
+                    let mut digits = String::new();
+                    while let Some(c) = chars.peek().copied().filter(char::is_ascii_digit) {
+                        digits.push(c);
+                        chars.next();
+                    }
+                    digits
+                };
+                let x_num = take_number(&mut a_chars);
+                let y_num = take_number(&mut b_chars);
+                let x_trimmed = x_num.trim_start_matches('0');
+                let y_trimmed = y_num.trim_start_matches('0');
+                let ordering = x_trimmed
+                    .len()
+                    .cmp(&y_trimmed.len())
+                    .then_with(|| x_trimmed.cmp(y_trimmed));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            (Some(x), Some(y)) => {
+                let ordering = x.to_lowercase().cmp(y.to_lowercase());
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+                a_chars.next();
+                b_chars.next();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn natural_order() {
+        let mut names = vec!["file10", "File2", "file1", "file02", "a", "file1b"];
+        names.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(names, ["a", "file1", "file1b", "File2", "file02", "file10"]);
+    }
+
+    #[test]
+    fn numbers_longer_than_u64() {
+        assert_eq!(
+            natural_cmp("v99999999999999999999", "v100000000000000000000"),
+            Ordering::Less
+        );
+    }
+
+    #[test]
+    fn ties_fall_back_to_plain_comparison() {
+        assert_eq!(natural_cmp("a", "A"), Ordering::Greater);
+        assert_eq!(natural_cmp("a1", "a01"), Ordering::Greater);
+        assert_eq!(natural_cmp("abc", "abc"), Ordering::Equal);
+    }
+}
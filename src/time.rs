@@ -0,0 +1,59 @@
+//! Timestamps kept by the filesystem.
+
+use std::{fs::Metadata, time::SystemTime};
+
+/// Which of an entry's timestamps to show or sort by.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TimeField {
+    /// Last modification of the contents.
+    #[default]
+    Mtime,
+    /// Last access.
+    Atime,
+    /// Creation ("birth"). Not every filesystem records it.
+    Btime,
+    /// Last change to the contents or the inode, such as permissions or
+    /// links. Unix only.
+    Ctime,
+}
+
+impl TimeField {
+    /// Reads the timestamp from `metadata`, or `None` if the platform or
+    /// filesystem does not provide it.
+    pub fn of(self, metadata: &Metadata) -> Option<SystemTime> {
+        match self {
+            TimeField::Mtime => metadata.modified().ok(),
+            TimeField::Atime => metadata.accessed().ok(),
+            TimeField::Btime => metadata.created().ok(),
+            TimeField::Ctime => changed(metadata),
+        }
+    }
+
+    /// What the timestamp records, as a past participle: "modified",
+    /// "accessed", "created" or "changed".
+    pub fn verb(self) -> &'static str {
+        match self {
+            TimeField::Mtime => "modified",
+            TimeField::Atime => "accessed",
+            TimeField::Btime => "created",
+            TimeField::Ctime => "changed",
+        }
+    }
+}
+
+#[cfg(unix)]
+fn changed(metadata: &Metadata) -> Option<SystemTime> {
+    use std::{os::unix::fs::MetadataExt, time::Duration};
+    let nanos = Duration::from_nanos(metadata.ctime_nsec() as u64);
+    match u64::try_from(metadata.ctime()) {
+        Ok(secs) => SystemTime::UNIX_EPOCH.checked_add(Duration::from_secs(secs) + nanos),
+        Err(_) => SystemTime::UNIX_EPOCH
+            .checked_sub(Duration::from_secs(metadata.ctime().unsigned_abs()))?
+            .checked_add(nanos),
+    }
+}
+
+#[cfg(not(unix))]
+fn changed(_metadata: &Metadata) -> Option<SystemTime> {
+    None
+}
@@ -0,0 +1,919 @@
+//! Lazy directory traversal.
+
+use crate::{
+    ignore::{self, IgnoreFile},
+    pattern::Pattern,
+    sort::{sort_entries, SortItem, SortKey, SortOrder},
+};
+use std::{
+    fs::{self, FileType, Metadata},
+    io,
+    iter::Peekable,
+    path::{Path, PathBuf},
+    vec,
+};
+
+/// Builds a directory walk over one or more roots.
+///
+/// ```no_run
+/// use dirr::{SortKey, Walker};
+///
+/// for entry in Walker::new(".").max_depth(2).sort_by(SortKey::Size) {
+///     let entry = entry?;
+///     println!("{}{}", "  ".repeat(entry.depth()), entry.file_name());
+/// }
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Clone, Debug)]
+pub struct Walker {
+    roots: Vec<PathBuf>,
+    options: WalkOptions,
+}
+
+/// The kinds of entry [`Walker::kind`] can select.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EntryKind {
+    File,
+    Dir,
+    Symlink,
+}
+
+/// How [`Walker::disk_usage`] measures the entries it adds up.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DiskUsage {
+    /// File lengths, as shown by `ls -l`.
+    #[default]
+    Apparent,
+    /// Disk blocks actually allocated, as counted by `du`. Directories' own
+    /// blocks are included, and sparse files count only what they use.
+    Allocated,
+}
+
+impl DiskUsage {
+    /// The size of a single entry, not counting anything beneath it.
+    fn size_of(self, metadata: &Metadata) -> u64 {
+        match self {
+            DiskUsage::Apparent if metadata.is_dir() => 0,
+            DiskUsage::Apparent => metadata.len(),
+            DiskUsage::Allocated => allocated_size(metadata),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn allocated_size(metadata: &Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    // `st_blocks` is always in 512-byte units, whatever the block size.
+    metadata.blocks() * 512
+}
+
+#[cfg(not(unix))]
+fn allocated_size(metadata: &Metadata) -> u64 {
+    metadata.len()
+}
+
+#[derive(Clone, Debug, Default)]
+struct WalkOptions {
+    exclude: Vec<Pattern>,
+    include: Vec<Pattern>,
+    kinds: Vec<EntryKind>,
+    extensions: Vec<String>,
+    max_depth: Option<usize>,
+    sort: SortOrder,
+    follow_links: bool,
+    ignore_files: bool,
+    skip_hidden: bool,
+    disk_usage: Option<DiskUsage>,
+}
+
+impl Walker {
+    pub fn new<P: AsRef<Path>>(root: P) -> Walker {
+        Walker {
+            roots: vec![root.as_ref().to_path_buf()],
+            options: WalkOptions::default(),
+        }
+    }
+
+    /// Adds another root, walked after the ones already added.
+    pub fn root<P: AsRef<Path>>(mut self, root: P) -> Walker {
+        self.roots.push(root.as_ref().to_path_buf());
+        self
+    }
+
+    /// Skips entries matching `pattern`, and everything below them.
+    ///
+    /// See [`Pattern`] for when it is matched against an entry's name and
+    /// when against its path relative to the root.
+    pub fn exclude<P: Into<Pattern>>(mut self, pattern: P) -> Walker {
+        self.options.exclude.push(pattern.into());
+        self
+    }
+
+    /// Lists only entries matching `pattern` (or any other include pattern),
+    /// plus the directories leading to them.
+    ///
+    /// Like the other selection filters, this needs a directory's whole
+    /// subtree before the directory can be listed, so filtered walks are
+    /// buffered one root at a time rather than fully lazy.
+    pub fn include<P: Into<Pattern>>(mut self, pattern: P) -> Walker {
+        self.options.include.push(pattern.into());
+        self
+    }
+
+    /// Lists only entries of `kind` (or any other selected kind), plus the
+    /// directories leading to them.
+    pub fn kind(mut self, kind: EntryKind) -> Walker {
+        self.options.kinds.push(kind);
+        self
+    }
+
+    /// Lists only entries with extension `ext` (or any other selected
+    /// extension, compared case-insensitively), plus the directories leading
+    /// to them.
+    pub fn extension(mut self, ext: &str) -> Walker {
+        let ext = ext.trim_start_matches('.').to_lowercase();
+        self.options.extensions.push(ext);
+        self
+    }
+
+    /// Lists entries at most `depth` levels below each root.
+    ///
+    /// Directories at the limit are not descended into; their direct entries
+    /// are only counted, see [`Entry::hidden`].
+    pub fn max_depth(mut self, depth: usize) -> Walker {
+        self.options.max_depth = Some(depth);
+        self
+    }
+
+    pub fn sort(mut self, order: SortOrder) -> Walker {
+        self.options.sort = order;
+        self
+    }
+
+    pub fn sort_by(mut self, key: SortKey) -> Walker {
+        self.options.sort.key = key;
+        self
+    }
+
+    /// Whether to report and descend into the targets of symbolic links
+    /// rather than the links themselves. Off by default.
+    ///
+    /// Roots are always followed. A link back to a directory that is already
+    /// being walked is not descended into again, see [`Entry::is_cycle`].
+    pub fn follow_links(mut self, follow: bool) -> Walker {
+        self.options.follow_links = follow;
+        self
+    }
+
+    /// Whether to skip entries listed in `.gitignore`, `.ignore` and
+    /// `.dirrignore` files. Off by default.
+    ///
+    /// Git's own ignore sources (`.gitignore`, `.git/info/exclude` and the
+    /// global `core.excludesFile`) are only consulted inside a git work tree,
+    /// where the `.git` directory itself is skipped as well.
+    pub fn ignore_files(mut self, enabled: bool) -> Walker {
+        self.options.ignore_files = enabled;
+        self
+    }
+
+    /// Whether to skip hidden entries: those whose name starts with a dot,
+    /// and those marked hidden by the platform (the hidden attribute on
+    /// Windows, the `UF_HIDDEN` flag on macOS). Off by default.
+    ///
+    /// Roots are always listed, even if they are hidden themselves.
+    pub fn skip_hidden(mut self, skip: bool) -> Walker {
+        self.options.skip_hidden = skip;
+        self
+    }
+
+    /// Adds up the size of everything beneath each directory, see
+    /// [`Entry::total_size`].
+    ///
+    /// Excluded, ignored and hidden entries are not counted, and neither are
+    /// entries the selection filters leave out. Entries below
+    /// [`max_depth`](Walker::max_depth) are counted even though they are not
+    /// listed, so the walk reads whole subtrees and is buffered one root at a
+    /// time.
+    pub fn disk_usage(mut self, mode: DiskUsage) -> Walker {
+        self.options.disk_usage = Some(mode);
+        self
+    }
+}
+
+impl IntoIterator for Walker {
+    type Item = io::Result<Entry>;
+    type IntoIter = Walk;
+
+    fn into_iter(self) -> Walk {
+        Walk {
+            options: self.options,
+            roots: self.roots.into_iter(),
+            root: PathBuf::new(),
+            stack: Vec::new(),
+            in_git: false,
+            root_ignores: Vec::new(),
+            buffer: Vec::new().into_iter(),
+        }
+    }
+}
+
+/// A single file or directory reached by a [`Walk`].
+#[derive(Debug)]
+pub struct Entry {
+    path: PathBuf,
+    depth: usize,
+    metadata: Option<Metadata>,
+    is_last: bool,
+    hidden: Option<usize>,
+    /// Whether any of the `hidden` entries passes the selection filters, so
+    /// that `prune` keeps the directory.
+    hidden_selected: bool,
+    error: Option<io::Error>,
+    link: Option<Link>,
+    cycle: bool,
+    total_size: Option<u64>,
+}
+
+#[derive(Debug)]
+struct Link {
+    /// `None` if the link itself could not be read.
+    target: Option<PathBuf>,
+    broken: bool,
+}
+
+impl Entry {
+    /// The entry's path, starting with the root it was found under.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn into_path(self) -> PathBuf {
+        self.path
+    }
+
+    /// The entry's name, or the whole path for roots such as `.` or `/`.
+    pub fn file_name(&self) -> String {
+        self.path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_else(|| self.path.display().to_string())
+    }
+
+    /// 0 for roots, 1 for their direct entries, and so on.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Metadata fetched while walking, or `None` if it could not be read.
+    ///
+    /// For symbolic links this describes the link itself, unless the walk
+    /// follows links and the target exists.
+    pub fn metadata(&self) -> Option<&Metadata> {
+        self.metadata.as_ref()
+    }
+
+    pub fn file_type(&self) -> Option<FileType> {
+        self.metadata.as_ref().map(Metadata::file_type)
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.metadata.as_ref().is_some_and(Metadata::is_dir)
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        self.link.is_some()
+    }
+
+    /// Where a symbolic link points, as stored in the link.
+    pub fn link_target(&self) -> Option<&Path> {
+        self.link.as_ref().and_then(|l| l.target.as_deref())
+    }
+
+    /// Whether this is a symbolic link whose target does not exist.
+    pub fn is_broken_link(&self) -> bool {
+        self.link.as_ref().is_some_and(|l| l.broken)
+    }
+
+    /// Whether this directory was not descended into because it is already
+    /// being walked higher up, i.e. following it would loop forever.
+    pub fn is_cycle(&self) -> bool {
+        self.cycle
+    }
+
+    /// Whether this is the last entry listed in its parent directory.
+    pub fn is_last(&self) -> bool {
+        self.is_last
+    }
+
+    /// For directories cut off by the depth limit, how many entries they contain.
+    pub fn hidden(&self) -> Option<usize> {
+        self.hidden
+    }
+
+    /// With [`Walker::disk_usage`], the size of the entry plus, for a
+    /// directory, everything counted beneath it.
+    pub fn total_size(&self) -> Option<u64> {
+        self.total_size
+    }
+
+    /// Why the entry's metadata or, for directories, its contents could not be
+    /// read. The walk carries on past such entries; a directory whose listing
+    /// failed part-way still yields the entries read before the failure.
+    pub fn error(&self) -> Option<&io::Error> {
+        self.error.as_ref()
+    }
+}
+
+/// A directory entry that has been read and inspected but not yet visited.
+struct Child {
+    path: PathBuf,
+    metadata: io::Result<Metadata>,
+    link: Option<Link>,
+}
+
+impl SortItem for Child {
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn metadata(&self) -> Option<&Metadata> {
+        self.metadata.as_ref().ok()
+    }
+}
+
+/// Identifies a directory independently of the path it was reached by.
+#[cfg(unix)]
+type DirId = (u64, u64);
+#[cfg(not(unix))]
+type DirId = PathBuf;
+
+#[cfg(unix)]
+fn dir_id(_path: &Path, metadata: &Metadata) -> Option<DirId> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn dir_id(path: &Path, _metadata: &Metadata) -> Option<DirId> {
+    fs::canonicalize(path).ok()
+}
+
+/// Whether a directory entry is a dotfile or carries the platform's hidden
+/// marker.
+fn is_hidden(entry: &fs::DirEntry) -> bool {
+    entry.file_name().to_string_lossy().starts_with('.') || has_hidden_flag(entry)
+}
+
+#[cfg(windows)]
+fn has_hidden_flag(entry: &fs::DirEntry) -> bool {
+    use std::os::windows::fs::MetadataExt;
+    const FILE_ATTRIBUTE_HIDDEN: u32 = 0x2;
+    entry
+        .metadata()
+        .is_ok_and(|m| m.file_attributes() & FILE_ATTRIBUTE_HIDDEN != 0)
+}
+
+#[cfg(target_os = "macos")]
+fn has_hidden_flag(entry: &fs::DirEntry) -> bool {
+    use std::os::macos::fs::MetadataExt;
+    const UF_HIDDEN: u32 = 0x8000;
+    entry
+        .metadata()
+        .is_ok_and(|m| m.st_flags() & UF_HIDDEN != 0)
+}
+
+#[cfg(not(any(windows, target_os = "macos")))]
+fn has_hidden_flag(_entry: &fs::DirEntry) -> bool {
+    false
+}
+
+/// A directory's filtered and sorted entries, any error that cut the listing
+/// short, and the directory's own ignore files.
+type Listing = (vec::IntoIter<Child>, Option<io::Error>, Vec<IgnoreFile>);
+
+/// The listing of one directory that is being walked.
+struct Frame {
+    children: vec::IntoIter<Child>,
+    depth: usize,
+    dir: Option<DirId>,
+    /// Ignore files found in the directory itself.
+    ignores: Vec<IgnoreFile>,
+}
+
+/// Iterator over the entries of a [`Walker`], in display order.
+///
+/// Each root is yielded first at depth 0, and a directory is yielded before
+/// its contents. Directories are read one at a time as the walk reaches them,
+/// so only the listings along the current path are held in memory.
+///
+/// Only a root that cannot be read is yielded as an `Err`; problems further
+/// down are attached to the affected entry, see [`Entry::error`].
+pub struct Walk {
+    options: WalkOptions,
+    roots: vec::IntoIter<PathBuf>,
+    /// The root currently being walked.
+    root: PathBuf,
+    stack: Vec<Frame>,
+    /// Whether the current root is inside a git work tree.
+    in_git: bool,
+    /// Ignore files that apply to the whole of the current root.
+    root_ignores: Vec<IgnoreFile>,
+    /// The pruned entries of the current root, when selection filters are set.
+    buffer: vec::IntoIter<Entry>,
+}
+
+impl Iterator for Walk {
+    type Item = io::Result<Entry>;
+
+    fn next(&mut self) -> Option<io::Result<Entry>> {
+        if !self.options.buffers() {
+            return match self.next_in_root() {
+                Some(entry) => Some(Ok(entry)),
+                None => self.next_root(),
+            };
+        }
+
+        if let Some(entry) = self.buffer.next() {
+            return Some(Ok(entry));
+        }
+        let mut entries = match self.next_root()? {
+            Ok(root) => vec![root],
+            Err(e) => return Some(Err(e)),
+        };
+        entries.extend(std::iter::from_fn(|| self.next_in_root()));
+        // Prune first so directory totals only count what is listed.
+        if self.options.selects_some() {
+            prune(&mut entries, &self.root, &self.options);
+        }
+        if let Some(mode) = self.options.disk_usage {
+            sum_sizes(&mut entries, mode);
+            // Siblings were sorted before their totals were known.
+            if self.options.sort.key == SortKey::Size {
+                entries = sort_by_totals(entries, self.options.sort);
+            }
+        }
+        if let (Some(_), Some(max_depth)) = (self.options.disk_usage, self.options.max_depth) {
+            trim(&mut entries, max_depth);
+        }
+        self.buffer = entries.into_iter();
+        self.buffer.next().map(Ok)
+    }
+}
+
+impl Walk {
+    /// The next entry below the current root, or `None` once it is done.
+    fn next_in_root(&mut self) -> Option<Entry> {
+        loop {
+            let frame = self.stack.last_mut()?;
+            let Some(child) = frame.children.next() else {
+                self.stack.pop();
+                continue;
+            };
+            let depth = frame.depth;
+            let is_last = frame.children.len() == 0;
+            return Some(self.visit(child, depth, is_last));
+        }
+    }
+
+    fn next_root(&mut self) -> Option<io::Result<Entry>> {
+        let root = self.roots.next()?;
+        Some(self.enter_root(root))
+    }
+
+    fn enter_root(&mut self, root: PathBuf) -> io::Result<Entry> {
+        let metadata = fs::metadata(&root)?;
+        self.root = root.clone();
+        if self.options.ignore_files {
+            (self.in_git, self.root_ignores) = ignore::root_ignores(&root);
+        }
+        let dir = dir_id(&root, &metadata);
+        let mut entry = Entry {
+            path: root,
+            depth: 0,
+            metadata: Some(metadata),
+            is_last: true,
+            hidden: None,
+            hidden_selected: false,
+            error: None,
+            link: None,
+            cycle: false,
+            total_size: None,
+        };
+        if entry.is_dir() {
+            if self.options.at_limit(0) {
+                match self.count_entries(&entry.path) {
+                    Ok((count, selected)) => {
+                        entry.hidden = Some(count);
+                        entry.hidden_selected = selected;
+                    }
+                    Err(e) => entry.error = Some(e),
+                }
+            } else {
+                let (children, error, ignores) = self.read_children(&entry.path)?;
+                entry.error = error;
+                self.stack.push(Frame {
+                    children,
+                    depth: 1,
+                    dir,
+                    ignores,
+                });
+            }
+        }
+        Ok(entry)
+    }
+
+    fn visit(&mut self, child: Child, depth: usize, is_last: bool) -> Entry {
+        let mut entry = Entry {
+            path: child.path,
+            depth,
+            metadata: None,
+            is_last,
+            hidden: None,
+            hidden_selected: false,
+            error: None,
+            link: child.link,
+            cycle: false,
+            total_size: None,
+        };
+        match child.metadata {
+            Ok(metadata) => entry.metadata = Some(metadata),
+            Err(e) => entry.error = Some(e),
+        }
+
+        let Some(metadata) = entry.metadata.as_ref().filter(|m| m.is_dir()) else {
+            return entry;
+        };
+        let dir = dir_id(&entry.path, metadata);
+        if dir.is_some() && self.stack.iter().any(|frame| frame.dir == dir) {
+            entry.cycle = true;
+        } else if self.options.at_limit(depth) {
+            match self.count_entries(&entry.path) {
+                Ok((count, selected)) => {
+                    entry.hidden = Some(count);
+                    entry.hidden_selected = selected;
+                }
+                Err(e) => entry.error = Some(e),
+            }
+        } else {
+            match self.read_children(&entry.path) {
+                Ok((children, error, ignores)) => {
+                    entry.error = error;
+                    self.stack.push(Frame {
+                        children,
+                        depth: depth + 1,
+                        dir,
+                        ignores,
+                    });
+                }
+                Err(e) => entry.error = Some(e),
+            }
+        }
+
+        entry
+    }
+
+    /// Reads, filters and sorts the entries of `path`, and loads its ignore
+    /// files. Fails only if the directory cannot be opened; an error part-way
+    /// through the listing is returned alongside the entries read before it.
+    fn read_children(&self, path: &Path) -> io::Result<Listing> {
+        let listing = fs::read_dir(path)?;
+        let ignores = if self.options.ignore_files {
+            ignore::dir_ignores(path, self.in_git)
+        } else {
+            Vec::new()
+        };
+        let mut children = Vec::new();
+        let mut error = None;
+
+        for entry_result in listing {
+            match entry_result {
+                Ok(entry) => {
+                    let current_path = entry.path();
+                    if self.in_git && self.options.ignore_files && entry.file_name() == ".git" {
+                        continue;
+                    }
+                    let relative = relative_path(&self.root, &current_path);
+                    if self
+                        .options
+                        .exclude
+                        .iter()
+                        .any(|p| p.matches_path(&relative))
+                    {
+                        continue;
+                    }
+                    if self.options.skip_hidden && is_hidden(&entry) {
+                        continue;
+                    }
+                    let child = self.options.inspect(current_path);
+                    // Directories are kept so the walk can look for selected
+                    // entries inside them; `prune` drops them later if none turn up.
+                    let is_dir = child.metadata().is_some_and(Metadata::is_dir);
+                    let selected = is_dir
+                        || self.options.selects(
+                            &relative,
+                            &child.path,
+                            child.metadata(),
+                            child.link.is_some(),
+                        );
+                    if selected && !self.is_ignored(&child.path, is_dir, &ignores) {
+                        children.push(child);
+                    }
+                }
+                Err(e) => {
+                    error = Some(e);
+                    break;
+                }
+            }
+        }
+
+        sort_entries(&mut children, self.options.sort);
+        Ok((children.into_iter(), error, ignores))
+    }
+
+    /// Checks `path` against the ignore files of the root, of every
+    /// directory being walked, and `own`, those of its parent.
+    fn is_ignored(&self, path: &Path, is_dir: bool, own: &[IgnoreFile]) -> bool {
+        if !self.options.ignore_files {
+            return false;
+        }
+        let files = self
+            .root_ignores
+            .iter()
+            .chain(self.stack.iter().flat_map(|frame| &frame.ignores))
+            .chain(own);
+        ignore::is_ignored(files, path, is_dir)
+    }
+
+    /// Counts the entries of a directory the walk does not descend into, and
+    /// says whether any of them passes the selection filters.
+    ///
+    /// As in `read_children`, directories are counted even if they do not
+    /// pass the filters themselves. Without filters only names and file
+    /// types are read.
+    fn count_entries(&self, path: &Path) -> io::Result<(usize, bool)> {
+        let ignores = if self.options.ignore_files {
+            ignore::dir_ignores(path, self.in_git)
+        } else {
+            Vec::new()
+        };
+        let mut count = 0;
+        let mut any_selected = false;
+        for entry in fs::read_dir(path)? {
+            let entry = entry?;
+            if self.in_git && self.options.ignore_files && entry.file_name() == ".git" {
+                continue;
+            }
+            let current_path = entry.path();
+            let relative = relative_path(&self.root, &current_path);
+            if self
+                .options
+                .exclude
+                .iter()
+                .any(|p| p.matches_path(&relative))
+            {
+                continue;
+            }
+            if self.options.skip_hidden && is_hidden(&entry) {
+                continue;
+            }
+            let is_dir = entry.file_type().is_ok_and(|t| t.is_dir());
+            if self.is_ignored(&current_path, is_dir, &ignores) {
+                continue;
+            }
+            if self.options.selects_some() {
+                let child = self.options.inspect(current_path);
+                let selected = self.options.selects(
+                    &relative,
+                    &child.path,
+                    child.metadata(),
+                    child.link.is_some(),
+                );
+                if !selected && !child.metadata().is_some_and(Metadata::is_dir) {
+                    continue;
+                }
+                any_selected |= selected;
+            }
+            count += 1;
+        }
+        Ok((count, any_selected))
+    }
+}
+
+impl WalkOptions {
+    /// Fetches the metadata for `path`, resolving it if it is a symbolic link.
+    fn inspect(&self, path: PathBuf) -> Child {
+        let metadata = fs::symlink_metadata(&path);
+        if !metadata.as_ref().is_ok_and(|m| m.file_type().is_symlink()) {
+            return Child {
+                path,
+                metadata,
+                link: None,
+            };
+        }
+
+        let target = fs::read_link(&path).ok();
+        let resolved = fs::metadata(&path);
+        let broken = resolved.is_err();
+        let metadata = match resolved {
+            Ok(resolved) if self.follow_links => Ok(resolved),
+            _ => metadata,
+        };
+        Child {
+            path,
+            metadata,
+            link: Some(Link { target, broken }),
+        }
+    }
+
+    /// Whether any of the include, kind or extension filters are set.
+    fn selects_some(&self) -> bool {
+        !self.include.is_empty() || !self.kinds.is_empty() || !self.extensions.is_empty()
+    }
+
+    /// Whether the walk must read a whole root before yielding any of it.
+    fn buffers(&self) -> bool {
+        self.selects_some() || self.disk_usage.is_some()
+    }
+
+    /// Whether an entry, with `relative` its path from the root, passes the
+    /// include, kind and extension filters.
+    fn selects(
+        &self,
+        relative: &str,
+        path: &Path,
+        metadata: Option<&Metadata>,
+        is_symlink: bool,
+    ) -> bool {
+        let included =
+            self.include.is_empty() || self.include.iter().any(|p| p.matches_path(relative));
+
+        let kind_matches = self.kinds.is_empty()
+            || self.kinds.iter().any(|kind| match kind {
+                EntryKind::File => metadata.is_some_and(Metadata::is_file),
+                EntryKind::Dir => metadata.is_some_and(Metadata::is_dir),
+                EntryKind::Symlink => is_symlink,
+            });
+
+        let ext_matches = self.extensions.is_empty()
+            || path
+                .extension()
+                .map(|e| e.to_string_lossy().to_lowercase())
+                .is_some_and(|ext| self.extensions.contains(&ext));
+
+        included && kind_matches && ext_matches
+    }
+
+    /// Whether a directory at `depth` is cut off rather than read. When
+    /// adding up sizes the whole tree is read, and `trim` cuts it afterwards.
+    fn at_limit(&self, depth: usize) -> bool {
+        self.disk_usage.is_none() && self.max_depth.is_some_and(|max| depth >= max)
+    }
+}
+
+/// Drops directories from a root's entries that neither pass the selection
+/// filters themselves nor lead to an entry that does, then recomputes which
+/// entries are last among their siblings.
+fn prune(entries: &mut Vec<Entry>, root: &Path, options: &WalkOptions) {
+    // `entries` is in pre-order, so walking it backwards sees every entry's
+    // descendants before the entry itself.
+    let mut keep = vec![false; entries.len()];
+    let mut kept_below: Vec<bool> = Vec::new();
+    for (i, entry) in entries.iter().enumerate().rev() {
+        let depth = entry.depth;
+        if kept_below.len() < depth + 2 {
+            kept_below.resize(depth + 2, false);
+        }
+        let has_kept_child = std::mem::take(&mut kept_below[depth + 1]);
+        keep[i] = depth == 0
+            || !entry.is_dir()
+            || has_kept_child
+            || entry.hidden_selected
+            || options.selects(
+                &relative_path(root, &entry.path),
+                &entry.path,
+                entry.metadata(),
+                entry.is_symlink(),
+            );
+        kept_below[depth] |= keep[i];
+    }
+
+    let mut keep = keep.into_iter();
+    entries.retain(|_| keep.next().unwrap_or(false));
+    mark_last(entries);
+}
+
+/// Recomputes which of a root's entries are last among their siblings.
+fn mark_last(entries: &mut [Entry]) {
+    let mut seen_sibling: Vec<bool> = Vec::new();
+    for entry in entries.iter_mut().rev() {
+        let depth = entry.depth;
+        if seen_sibling.len() < depth + 2 {
+            seen_sibling.resize(depth + 2, false);
+        }
+        entry.is_last = !seen_sibling[depth];
+        seen_sibling[depth] = true;
+        seen_sibling[depth + 1] = false;
+    }
+}
+
+/// Sets the total size of each of a root's entries in one post-order pass.
+fn sum_sizes(entries: &mut [Entry], mode: DiskUsage) {
+    // As in `prune`, walking the pre-order entries backwards sees every
+    // entry's descendants before the entry itself.
+    let mut below: Vec<u64> = Vec::new();
+    for entry in entries.iter_mut().rev() {
+        let depth = entry.depth;
+        if below.len() < depth + 2 {
+            below.resize(depth + 2, 0);
+        }
+        let children = std::mem::take(&mut below[depth + 1]);
+        let own = entry.metadata.as_ref().map_or(0, |m| mode.size_of(m));
+        let total = own + children;
+        entry.total_size = Some(total);
+        below[depth] += total;
+    }
+}
+
+/// An entry with everything beneath it, for moving whole subtrees around.
+struct Subtree {
+    entry: Entry,
+    children: Vec<Subtree>,
+}
+
+impl Subtree {
+    /// Takes `entry`'s descendants off the front of the pre-order `rest`.
+    fn new(entry: Entry, rest: &mut Peekable<vec::IntoIter<Entry>>) -> Subtree {
+        let mut children = Vec::new();
+        while let Some(child) = rest.next_if(|e| e.depth > entry.depth) {
+            children.push(Subtree::new(child, rest));
+        }
+        Subtree { entry, children }
+    }
+
+    fn flatten_sorted(self, order: SortOrder, out: &mut Vec<Entry>) {
+        let mut children = self.children;
+        sort_entries(&mut children, order);
+        out.push(self.entry);
+        for child in children {
+            child.flatten_sorted(order, out);
+        }
+    }
+}
+
+impl SortItem for Subtree {
+    fn path(&self) -> &Path {
+        &self.entry.path
+    }
+
+    fn metadata(&self) -> Option<&Metadata> {
+        self.entry.metadata()
+    }
+
+    fn total_size(&self) -> Option<u64> {
+        self.entry.total_size
+    }
+}
+
+/// Sorts a root's entries again once `sum_sizes` has set their totals,
+/// keeping each entry's descendants right after it.
+fn sort_by_totals(entries: Vec<Entry>, order: SortOrder) -> Vec<Entry> {
+    let mut sorted = Vec::with_capacity(entries.len());
+    let mut rest = entries.into_iter().peekable();
+    while let Some(entry) = rest.next() {
+        Subtree::new(entry, &mut rest).flatten_sorted(order, &mut sorted);
+    }
+    mark_last(&mut sorted);
+    sorted
+}
+
+/// Drops the entries below `max_depth` that were only read to add up sizes,
+/// marking directories at the limit with how many entries they hide.
+fn trim(entries: &mut Vec<Entry>, max_depth: usize) {
+    let mut at_limit = None;
+    for i in 0..entries.len() {
+        let depth = entries[i].depth;
+        if depth == max_depth && entries[i].is_dir() && !entries[i].cycle {
+            entries[i].hidden = Some(0);
+            at_limit = Some(i);
+        } else if depth == max_depth + 1 {
+            if let Some(hidden) = at_limit.and_then(|j| entries[j].hidden.as_mut()) {
+                *hidden += 1;
+            }
+        }
+    }
+    entries.retain(|entry| entry.depth <= max_depth);
+}
+
+/// The `/`-separated path of `path` relative to `root`, which patterns are
+/// matched against.
+fn relative_path(root: &Path, path: &Path) -> String {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    let mut out = String::new();
+    for (i, component) in relative.components().enumerate() {
+        if i > 0 {
+            out.push('/');
+        }
+        out.push_str(&component.as_os_str().to_string_lossy());
+    }
+    out
+}